@@ -0,0 +1,35 @@
+//! Benchmarks the hot paths: parsing a response body, building the keys from it, and validating
+//! a token against warm keys, in full or for a single claim.
+//!
+//! The data is fixed (a recorded response, and a token that expires in 2100) so runs are comparable.
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+
+static SAMPLE: &[u8] = include_bytes!("../testdata/sample_response.http");
+
+fn bench_hot_paths(c: &mut Criterion) {
+	let body = &SAMPLE[google_pem::fetch::body(SAMPLE).unwrap()..];
+	c.bench_function("parse", |b| b.iter_batched_ref(
+		|| body.to_vec(),
+		|body| google_pem::parse(body).count(),
+		BatchSize::SmallInput,
+	));
+	c.bench_function("try_extend", |b| b.iter_batched_ref(
+		|| body.to_vec(),
+		|body| google_pem::keys::Keys::with_default_capacity().try_extend(google_pem::parse(body)).unwrap(),
+		BatchSize::SmallInput,
+	));
+
+	let header = Header { kid: Some("a".to_owned()), ..Header::new(Algorithm::RS256) };
+	let claims = serde_json::json!({ "iss": "https://accounts.google.com", "sub": "1234567890", "email": "user@example.com", "iat": 1706298589, "exp": 4102444800u64 });
+	let key = EncodingKey::from_rsa_pem(include_bytes!("../testdata/rsa_a.key")).unwrap();
+	let token = jsonwebtoken::encode(&header, &claims, &key).unwrap();
+	let mut keys = google_pem::keys::Keys::with_default_capacity();
+	keys.push(b"a", include_bytes!("../testdata/rsa_a.crt")).unwrap();
+	c.bench_function("validate", |b| b.iter(|| keys.validate::<serde_json::Value>(black_box(&token)).unwrap()));
+	c.bench_function("validate_claim", |b| b.iter(|| keys.validate_claim(black_box(&token), "email").unwrap()));
+}
+
+criterion_group!(benches, bench_hot_paths);
+criterion_main!(benches);
@@ -0,0 +1,21 @@
+//! Benchmarks finding the key of a token, against decoding its whole header.
+//!
+//! Validating a token of an unknown key stops right after finding its key, so it's mostly the
+//! key ID extraction.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+
+fn bench_kid(c: &mut Criterion) {
+	let header = Header { kid: Some("85e55107466b7e29836199c58c7581f5b923be44".to_owned()), ..Header::new(Algorithm::RS256) };
+	let key = EncodingKey::from_rsa_pem(include_bytes!("../testdata/rsa_a.key")).unwrap();
+	let token = jsonwebtoken::encode(&header, &serde_json::json!({ "sub": "1234567890" }), &key).unwrap();
+	let mut keys = google_pem::keys::Keys::with_default_capacity();
+	keys.push(b"a", include_bytes!("../testdata/rsa_a.crt")).unwrap();
+
+	c.bench_function("decode_header", |b| b.iter(|| jsonwebtoken::decode_header(black_box(&token)).unwrap()));
+	c.bench_function("validate_unknown_key", |b| b.iter(|| keys.validate::<serde_json::Value>(black_box(&token)).unwrap_err()));
+}
+
+criterion_group!(benches, bench_kid);
+criterion_main!(benches);
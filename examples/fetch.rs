@@ -1,4 +1,4 @@
-use std::{io::Write, time::SystemTime};
+use std::io::Write;
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() {
@@ -7,8 +7,7 @@ async fn main() {
     match google_pem::fetch::into(&mut buffer).await {
         Ok(len) => {
             let (age, body) = google_pem::fetch::process_headers(&buffer[..len]).unwrap();
-            let expiration = age.expiration_now::<SystemTime>();
-            println!("Google PEM (valid for {} seconds)", expiration.duration_since(SystemTime::now()).unwrap().as_secs());
+            println!("Google PEM (valid for {} seconds)", age.ttl());
             let body = &mut buffer[body..len];
             for (key_id, key_value) in google_pem::parse(body) {
                 let _ = out.write_all(key_id);
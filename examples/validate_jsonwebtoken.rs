@@ -16,17 +16,17 @@ async fn main() {
     std::io::stdout().flush().unwrap();
     std::io::stdin().read_line(&mut input).unwrap();
     let input = input.trim();
-    let key_id = jsonwebtoken::decode_header(&input).unwrap().kid.unwrap();
+    let key_id = jsonwebtoken::decode_header(input).unwrap().kid.unwrap();
     match google_pem::fetch::into(&mut buffer).await {
         Ok(len) => {
             let body = google_pem::fetch::body(&buffer).unwrap();
             for (id, key) in google_pem::parse(&mut buffer[body..len]) {
                 if id == key_id.as_bytes() {
-                    let decode = jsonwebtoken::DecodingKey::from_rsa_pem(&key).unwrap();
+                    let decode = jsonwebtoken::DecodingKey::from_rsa_pem(key).unwrap();
                     let mut validation = jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::RS256);
                     validation.set_issuer(&["accounts.google.com", "https://accounts.google.com"]);
                     validation.validate_aud = false;
-                    let data = jsonwebtoken::decode::<Claims>(&input, &decode, &validation).unwrap();
+                    let data = jsonwebtoken::decode::<Claims>(input, &decode, &validation).unwrap();
                     println!("{data:?}");
                     return;
                 }
@@ -15,7 +15,7 @@ async fn main() {
 	std::io::stdout().flush().unwrap();
 	std::io::stdin().read_line(&mut input).unwrap();
 	let input = input.trim();
-	let mut keys = google_pem::keys::Keys::default();
+	let mut keys = google_pem::keys::Keys::with_default_capacity();
 	keys.extend_fetch().await.unwrap();
 	let claims = keys.validate::<Claims>(input).unwrap();
 	println!("{claims:?}");
@@ -0,0 +1,15 @@
+//! Drives the parsers to completion over arbitrary bodies, which they mutate in place.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+	let mut body = data.to_vec();
+	for (id, key) in google_pem::parse(&mut body) {
+		assert!(id.len() + key.len() <= data.len());
+	}
+	let mut body = data.to_vec();
+	for _ in google_pem::try_parse(&mut body) {}
+	let mut body = data.to_vec();
+	if let Ok(parse) = google_pem::parse_checked(&mut body) { parse.for_each(drop); }
+});
@@ -0,0 +1,9 @@
+//! Unescapes arbitrary strings in place.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+	let mut s = data.to_vec();
+	assert!(google_pem::unescape(&mut s).len() <= data.len());
+});
@@ -1,48 +1,531 @@
 //! Caching [`Keys`].
 
-use std::mem::MaybeUninit;
+use std::{future::Future, mem::MaybeUninit, sync::Arc, time::Duration};
 
+use arc_swap::ArcSwap;
+use once_cell::sync::OnceCell;
 use serde::de::DeserializeOwned;
 
+use crate::{fetch::{Breaker, BreakerState, ErrorFetch, Google, KeyFetcher}, keys::KeysSnapshot};
+
 /// Caching [`crate::keys::Keys`].
-pub struct Keys<INSTANT = std::time::SystemTime> {
-	pub keys: crate::keys::Keys,
+///
+/// It isn't [`Clone`]: its [`snapshots`](Keys::snapshots) are shared with whoever asked for them,
+/// which a clone couldn't keep publishing to without the two caches racing each other.
+///
+/// It refreshes from its [`KeyFetcher`], which is [Google's endpoint](Google) unless constructed
+/// [`with_fetcher`](Keys::with_fetcher).
+pub struct Keys<INSTANT = std::time::SystemTime, const N: usize = { crate::keys::KEYS_CAPACITY }, FETCHER = Google> {
+	pub keys: crate::keys::Keys<N>,
+	/// Where the keys are refreshed from.
+	fetcher: FETCHER,
 	/// The expiration instant.
 	///
 	/// # Safety
 	/// `!self.keys.is_empty()` implies `self.expiration` is initialized.
 	expiration: MaybeUninit<INSTANT>,
+	/// The certs body the keys were last refreshed or restored from, for [persisting](Keys::persist) them.
+	certs: Option<Box<str>>,
+	/// The published snapshots, if anyone asked for them.
+	snapshots: OnceCell<Snapshots>,
+	/// The refresh notifications, if anyone subscribed.
+	refreshes: OnceCell<Refreshes<INSTANT>>,
+	/// The circuit breaker fetches go through, if any.
+	breaker: Option<Breaker<INSTANT>>,
+	/// How long to keep trying the previous keys after a refresh, in seconds.
+	grace_period: u64,
+	/// The minimum time to live of refreshed keys, in seconds.
+	min_ttl: u64,
+	/// The maximum time to live of refreshed keys, in seconds.
+	max_ttl: u64,
+	/// The keys before the last refresh, and until when they're still tried.
+	previous: Option<(KeysSnapshot, INSTANT)>,
+	/// Whether a [stale-while-revalidate](Keys::validate_swr) validation used expired keys, which
+	/// are due for a refresh.
+	refresh_pending: bool,
+}
+
+/// Shows the keys (but not their key material) and the expiration.
+impl<INSTANT: std::fmt::Debug, const N: usize, FETCHER> std::fmt::Debug for Keys<INSTANT, N, FETCHER> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("Keys")
+			.field("keys", &self.keys)
+			.field("expiration", &(!self.keys.is_empty()).then(|| unsafe { self.expiration.assume_init_ref() }))
+			.field("breaker", &self.breaker)
+			.field("grace_period", &self.grace_period)
+			.finish_non_exhaustive()
+	}
 }
 
-impl<INSTANT> Default for Keys<INSTANT> {
-	fn default() -> Self { Self::new() }
+impl<INSTANT, const N: usize, FETCHER: Default> Default for Keys<INSTANT, N, FETCHER> {
+	fn default() -> Self { Self::with_fetcher(FETCHER::default()) }
 }
 
-impl<INSTANT> Keys<INSTANT> {
+impl<INSTANT, const N: usize> Keys<INSTANT, N> {
 	/// New empty set of keys.
-	pub const fn new() -> Self {
+	pub const fn new() -> Self { Self::with_fetcher(Google) }
+}
+
+impl<INSTANT, const N: usize, FETCHER> Keys<INSTANT, N, FETCHER> {
+	/// New empty set of keys, which are refreshed from the fetcher.
+	pub const fn with_fetcher(fetcher: FETCHER) -> Self {
 		Self {
 			keys: crate::keys::Keys::new(),
+			fetcher,
 			expiration: MaybeUninit::uninit(),
+			certs: None,
+			snapshots: OnceCell::new(),
+			refreshes: OnceCell::new(),
+			breaker: None,
+			grace_period: 0,
+			min_ttl: 0,
+			max_ttl: u64::MAX,
+			previous: None,
+			refresh_pending: false,
+		}
+	}
+
+	/// Sets for how long, in seconds, tokens that the keys don't match are still tried against the
+	/// keys before the last refresh.
+	///
+	/// This keeps tokens signed by a just-retired key valid for a while. It's 0 (disabled) by default.
+	pub fn set_grace_period(&mut self, seconds: u64) { self.grace_period = seconds; }
+
+	/// Sets the minimum time, in seconds, to keep refreshed keys for, however short their `max-age`.
+	///
+	/// This keeps a tiny (e.g. mangled) `max-age` from refetching on nearly every validation. It's
+	/// 0 (none) by default.
+	pub fn set_min_ttl(&mut self, seconds: u64) { self.min_ttl = seconds; }
+
+	/// Sets the maximum time, in seconds, to keep refreshed keys for, however long their `max-age`.
+	///
+	/// It takes precedence over the [minimum](Self::set_min_ttl). There's none by default.
+	pub fn set_max_ttl(&mut self, seconds: u64) { self.max_ttl = seconds; }
+
+	/// Sets the circuit breaker fetches go through.
+	pub fn set_breaker(&mut self, breaker: Option<Breaker<INSTANT>>) { self.breaker = breaker; }
+
+	/// Gets the circuit breaker fetches go through.
+	pub fn breaker(&self) -> Option<&Breaker<INSTANT>> { self.breaker.as_ref() }
+
+	/// Gets a handle to the snapshots of the keys, which is updated whenever the keys are refreshed.
+	///
+	/// This lets request handlers validate tokens against the latest keys without borrowing the
+	/// cache (and so without locking it).
+	pub fn snapshots(&self) -> Snapshots {
+		self.snapshots.get_or_init(|| Snapshots(Arc::new(ArcSwap::from_pointee(self.keys.snapshot())))).clone()
+	}
+
+	/// Subscribes to the refreshes of the keys, each of which updates the receiver to the new
+	/// [`status`](Self::status).
+	///
+	/// The receiver starts at the current status.
+	pub fn on_refresh(&self) -> tokio::sync::watch::Receiver<Status<INSTANT>> where INSTANT: crate::fetch::Instant + Clone {
+		self.refreshes.get_or_init(|| Refreshes {
+			sender: tokio::sync::watch::channel(self.status()).0,
+			clone: INSTANT::clone,
+		}).sender.subscribe()
+	}
+
+	/// Publishes the current keys to the [`snapshots`](Self::snapshots) and the
+	/// [refresh subscribers](Self::on_refresh).
+	fn publish(&self) where INSTANT: crate::fetch::Instant {
+		if let Some(snapshots) = self.snapshots.get() {
+			snapshots.0.store(Arc::new(self.keys.snapshot()));
 		}
+		if let Some(refreshes) = self.refreshes.get() {
+			let expires_at = (!self.keys.is_empty()).then(|| (refreshes.clone)(unsafe { self.expiration.assume_init_ref() }));
+			refreshes.sender.send_replace(Status { key_count: self.keys.len(), expires_at, breaker: self.breaker_state() });
+		}
+	}
+
+	/// Gets the [`Status`] of the cache, e.g. for a health check that tells a warm cache from one
+	/// that never fetched any keys.
+	///
+	/// See [`keys::Keys::key_ids`](crate::keys::Keys::key_ids) for the IDs of the keys.
+	pub fn status(&self) -> Status<INSTANT> where INSTANT: crate::fetch::Instant + Clone {
+		Status {
+			key_count: self.keys.len(),
+			expires_at: (!self.keys.is_empty()).then(|| unsafe { self.expiration.assume_init_ref() }.clone()),
+			breaker: self.breaker_state(),
+		}
+	}
+
+	/// Gets the state of the [circuit breaker](Self::set_breaker), if there's one.
+	fn breaker_state(&self) -> Option<BreakerState> where INSTANT: crate::fetch::Instant {
+		self.breaker.as_ref().map(Breaker::state)
 	}
 
 	/// Checks if the cache is valid.
 	pub fn is_valid(&self) -> bool where INSTANT: crate::fetch::Instant {
-		!self.keys.is_empty() && unsafe { self.expiration.assume_init_ref() }.is_expired()
+		!self.keys.is_empty() && !unsafe { self.expiration.assume_init_ref() }.is_expired()
+	}
+
+	/// Gets the time until the keys expire, e.g. to schedule a [`refresh`](Self::refresh) ahead of
+	/// it, or `None` if no keys were fetched yet.
+	pub fn time_to_expiry(&self) -> Option<Duration> where INSTANT: crate::fetch::Instant + Clone {
+		(!self.keys.is_empty()).then(|| unsafe { self.expiration.assume_init_ref() }.time_left())
 	}
 
 	/// Validates a token.
-	pub async fn validate<Claims: DeserializeOwned>(&mut self, token: &str) -> Result<jsonwebtoken::TokenData<Claims>, Error> where INSTANT: crate::fetch::Instant {
+	///
+	/// See [`set_grace_period`](Self::set_grace_period) for falling back on the previous keys.
+	pub async fn validate<Claims: DeserializeOwned>(&mut self, token: &str) -> Result<jsonwebtoken::TokenData<Claims>, Error> where INSTANT: crate::fetch::Instant, FETCHER: KeyFetcher {
+		#[cfg(feature = "tracing")]
+		tracing::trace!(hit = self.is_valid(), "looked up the key cache");
+		self.refresh_if_expired().await?;
+		let result = validate(&self.keys, self.previous(), token);
+		#[cfg(feature = "tracing")]
+		if let Err(error) = &result { tracing::debug!(%error, "rejected a token"); }
+		Ok(result?)
+	}
+
+	/// Validates a token, stale-while-revalidate: expired keys are still used, without refreshing
+	/// them, for up to `max_stale` seconds after they expire.
+	///
+	/// This spares the validation the refresh's round-trip. Using expired keys instead flags a
+	/// refresh as [pending](Self::is_refresh_pending), for the caller to
+	/// [`refresh_if_pending`](Self::refresh_if_pending) the keys after the validation.
+	/// Since both borrow the cache mutably, they can't run concurrently: e.g. with the cache behind
+	/// a `tokio::sync::Mutex`, spawn a task that locks it and refreshes after the validation, while request
+	/// handlers that validate against the [`snapshots`](Self::snapshots) aren't blocked by it at all.
+	pub async fn validate_swr<Claims: DeserializeOwned>(&mut self, token: &str, max_stale: u64) -> Result<jsonwebtoken::TokenData<Claims>, Error> where INSTANT: crate::fetch::Instant + Clone, FETCHER: KeyFetcher {
+		if !self.is_stale_usable(max_stale) { self.refresh_if_expired().await?; }
+		else if !self.is_valid() { self.refresh_pending = true; }
+		Ok(validate(&self.keys, self.previous(), token)?)
+	}
+
+	/// Checks whether a [stale-while-revalidate](Self::validate_swr) validation used expired keys
+	/// since the last refresh.
+	pub fn is_refresh_pending(&self) -> bool { self.refresh_pending }
+
+	/// [Refreshes](Self::refresh) the keys if a refresh is [pending](Self::is_refresh_pending).
+	pub async fn refresh_if_pending(&mut self) -> Result<(), crate::keys::FetchExtendError> where INSTANT: crate::fetch::Instant, FETCHER: KeyFetcher {
+		if !self.refresh_pending { return Ok(()); }
+		self.refresh().await
+	}
+
+	/// Checks if the keys are present and expired at most `max_stale` seconds ago.
+	fn is_stale_usable(&self, max_stale: u64) -> bool where INSTANT: crate::fetch::Instant + Clone {
+		if self.keys.is_empty() { return false; }
+		let mut limit = unsafe { self.expiration.assume_init_ref() }.clone();
+		limit.add_seconds(max_stale);
+		!limit.is_expired()
+	}
+
+	/// Like [`validate`](Self::validate), but blocks rather than being `async`, for synchronous callers.
+	///
+	/// Refreshes run on a current-thread runtime of their own, so this must not be called from an
+	/// async context, where building the runtime panics.
+	#[cfg(feature = "blocking")]
+	pub fn validate_blocking<Claims: DeserializeOwned>(&mut self, token: &str) -> Result<jsonwebtoken::TokenData<Claims>, Error> where INSTANT: crate::fetch::Instant, FETCHER: KeyFetcher {
 		if !self.is_valid() {
-			self.keys.clear();
-			let (_, age) = self.keys.extend_fetch().await?;
-			self.expiration = MaybeUninit::new(age.expiration_now());
+			let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().map_err(Error::Runtime)?;
+			runtime.block_on(self.refresh_if_expired())?;
 		}
-		Ok(self.keys.validate(token)?)
+		Ok(validate(&self.keys, self.previous(), token)?)
+	}
+
+	/// Validates a token, retrying a failed refresh of the keys per the policy.
+	pub async fn validate_retry<Claims: DeserializeOwned>(&mut self, token: &str, retry: Retry) -> Result<jsonwebtoken::TokenData<Claims>, Error> where INSTANT: crate::fetch::Instant, FETCHER: KeyFetcher {
+		self.refresh_with(retry, tokio::time::sleep).await?;
+		Ok(validate(&self.keys, self.previous(), token)?)
+	}
+
+	/// Validates a batch of tokens, refreshing the keys at most once.
+	///
+	/// Fails only if the refresh does, otherwise returns the result of each token in order.
+	/// Batches of at least [`BATCH_PARALLEL_MIN`] tokens are validated across threads, on tokio's
+	/// blocking pool, so they don't hold up the runtime's own threads.
+	pub async fn validate_batch<Claims: DeserializeOwned + Send + 'static>(&mut self, tokens: &[&str]) -> Result<Vec<Result<jsonwebtoken::TokenData<Claims>, crate::keys::ValidateError>>, crate::keys::FetchExtendError> where INSTANT: crate::fetch::Instant, FETCHER: KeyFetcher {
+		let threads = std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get);
+		self.validate_batch_on(tokens, threads).await
+	}
+
+	/// Validates a batch of tokens, across the given count of threads for large batches.
+	async fn validate_batch_on<Claims: DeserializeOwned + Send + 'static>(&mut self, tokens: &[&str], threads: usize) -> Result<Vec<Result<jsonwebtoken::TokenData<Claims>, crate::keys::ValidateError>>, crate::keys::FetchExtendError> where INSTANT: crate::fetch::Instant, FETCHER: KeyFetcher {
+		self.refresh_if_expired().await?;
+		if tokens.len() < BATCH_PARALLEL_MIN || threads == 1 {
+			return Ok(tokens.iter().map(|token| validate(&self.keys, self.previous(), token)).collect());
+		}
+		let (keys, previous) = (self.keys.snapshot(), self.previous().cloned());
+		let tokens = tokens.iter().map(|&token| Box::<str>::from(token)).collect::<Vec<_>>();
+		let results = tokio::task::spawn_blocking(move || validate_parallel(&tokens, threads, |token| validate_snapshot(&keys, previous.as_ref(), token))).await;
+		Ok(results.unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic())))
+	}
+
+	/// Gets the previous keys, if they're within the grace period.
+	fn previous(&self) -> Option<&KeysSnapshot> where INSTANT: crate::fetch::Instant {
+		self.previous.as_ref()
+			.filter(|(_, until)| !until.is_expired())
+			.map(|(previous, _)| previous)
+	}
+
+	/// Keeps the current keys as the previous ones for the grace period.
+	fn retire(&mut self) where INSTANT: crate::fetch::Instant {
+		if self.grace_period == 0 || self.keys.is_empty() { return }
+		let mut until = INSTANT::now();
+		until.add_seconds(self.grace_period);
+		self.previous = Some((self.keys.snapshot(), until));
+	}
+
+	/// Fetches the keys and their expiration, whether or not the cache is valid, e.g. to warm it at
+	/// startup or on a timer so validations don't wait on a fetch.
+	///
+	/// If the refresh fails, the keys are left as they were, even if they're expired, e.g. for
+	/// [`validate_swr`](Self::validate_swr).
+	pub async fn refresh(&mut self) -> Result<(), crate::keys::FetchExtendError> where INSTANT: crate::fetch::Instant, FETCHER: KeyFetcher {
+		self.refetch_with(Retry::NONE, tokio::time::sleep).await
+	}
+
+	/// [Refreshes](Self::refresh) the keys if the cache isn't valid.
+	pub async fn refresh_if_expired(&mut self) -> Result<(), crate::keys::FetchExtendError> where INSTANT: crate::fetch::Instant, FETCHER: KeyFetcher {
+		self.refresh_with(Retry::NONE, tokio::time::sleep).await
+	}
+
+	/// Refreshes the keys if the cache isn't valid, with the given sleep between retries.
+	async fn refresh_with<Sleep>(&mut self, retry: Retry, sleep: impl Fn(Duration) -> Sleep) -> Result<(), crate::keys::FetchExtendError>
+	where
+		INSTANT: crate::fetch::Instant,
+		FETCHER: KeyFetcher,
+		Sleep: Future<Output = ()>,
+	{
+		if self.is_valid() { return Ok(()); }
+		self.refetch_with(retry, sleep).await
+	}
+
+	/// Refreshes the keys, with the given sleep between retries.
+	async fn refetch_with<Sleep>(&mut self, retry: Retry, sleep: impl Fn(Duration) -> Sleep) -> Result<(), crate::keys::FetchExtendError>
+	where
+		INSTANT: crate::fetch::Instant,
+		FETCHER: KeyFetcher,
+		Sleep: Future<Output = ()>,
+	{
+		let mut attempt = 0;
+		let (age, mut body) = loop {
+			let result = match &mut self.breaker {
+				Some(breaker) => breaker.call(self.fetcher.fetch()).await,
+				None => self.fetcher.fetch().await,
+			};
+			match result {
+				Ok(response) => break response,
+				Err(ErrorFetch::CircuitOpen) => return Err(ErrorFetch::CircuitOpen.into()),
+				Err(e) if attempt >= retry.retries => return Err(e.into()),
+				Err(_) => {
+					sleep(retry.delay(attempt)).await;
+					attempt += 1;
+				}
+			}
+		};
+		// Parse the keys aside, so a bad response leaves the last-known-good keys in place.
+		let certs = std::str::from_utf8(&body).ok().map(Box::from);
+		let mut keys = crate::keys::Keys::new();
+		keys.try_extend(crate::parse(&mut body))?;
+		self.retire();
+		self.keys = keys;
+		self.certs = certs;
+		let mut expiration = INSTANT::now();
+		expiration.add_seconds(age.ttl().max(self.min_ttl).min(self.max_ttl));
+		self.expiration = MaybeUninit::new(expiration);
+		self.refresh_pending = false;
+		self.publish();
+		Ok(())
 	}
 }
 
+impl<const N: usize, FETCHER> Keys<std::time::SystemTime, N, FETCHER> {
+	/// Gets the keys in a serializable form, e.g. to save them to disk and [`restore`](Self::restore)
+	/// them on startup rather than fetching them again.
+	///
+	/// Returns `None` if the keys weren't refreshed or restored (keys set directly can't be persisted).
+	pub fn persist(&self) -> Option<PersistedKeys> {
+		if self.keys.is_empty() { return None; }
+		Some(PersistedKeys {
+			certs: self.certs.as_deref()?.to_owned(),
+			expires_at: unsafe { *self.expiration.assume_init_ref() },
+		})
+	}
+
+	/// Restores [persisted](Self::persist) keys, replacing the current ones.
+	///
+	/// Keys that have expired since are restored as well, and refreshed on the next validation.
+	pub fn restore(&mut self, persisted: PersistedKeys) -> Result<(), jsonwebtoken::errors::Error> {
+		let mut body = persisted.certs.clone().into_bytes();
+		let mut keys = crate::keys::Keys::new();
+		keys.try_extend(crate::parse(&mut body))?;
+		self.keys = keys;
+		self.certs = Some(persisted.certs.into_boxed_str());
+		self.expiration = MaybeUninit::new(persisted.expires_at);
+		self.publish();
+		Ok(())
+	}
+}
+
+/// [`Keys`] in a serializable form; see [`Keys::persist`].
+///
+/// [`DecodingKey`](jsonwebtoken::DecodingKey)s can't be serialized, so it has the certs body they
+/// were parsed from.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct PersistedKeys {
+	/// The certs endpoint body.
+	pub certs: String,
+	/// When the keys expire.
+	pub expires_at: std::time::SystemTime,
+}
+
+/// The status of a [`Keys`] cache; see [`Keys::status`].
+#[derive(Debug, Hash, Clone, Copy, PartialEq, Eq)]
+pub struct Status<INSTANT> {
+	/// The count of cached keys.
+	pub key_count: usize,
+	/// When the keys expire, if there are any.
+	pub expires_at: Option<INSTANT>,
+	/// The state of the [circuit breaker](Keys::set_breaker), if there's one.
+	pub breaker: Option<BreakerState>,
+}
+
+/// The [`Keys::on_refresh`] subscriptions.
+struct Refreshes<INSTANT> {
+	sender: tokio::sync::watch::Sender<Status<INSTANT>>,
+	/// Clones the expiration into the statuses, which subscribing requires but refreshing doesn't.
+	clone: fn(&INSTANT) -> INSTANT,
+}
+
+/// A policy for retrying failed fetches with exponential backoff.
+#[derive(Debug, Hash, Clone, Copy, PartialEq, Eq)]
+pub struct Retry {
+	/// The number of retries after the first attempt.
+	pub retries: u32,
+	/// The delay before the first retry, which doubles for each one after.
+	pub base_delay: Duration,
+}
+
+impl Retry {
+	/// No retries.
+	pub const NONE: Self = Self { retries: 0, base_delay: Duration::ZERO };
+
+	/// Gets the delay before the retry that follows the given (0-based) attempt.
+	fn delay(&self, attempt: u32) -> Duration {
+		self.base_delay.saturating_mul(2u32.saturating_pow(attempt))
+	}
+}
+
+impl Default for Retry {
+	/// Three retries, starting at 100ms.
+	fn default() -> Self { Self { retries: 3, base_delay: Duration::from_millis(100) } }
+}
+
+/// The batch size from which [`Keys::validate_batch`] validates across threads.
+pub const BATCH_PARALLEL_MIN: usize = 64;
+
+/// Validates the token against the keys, or the previous keys if the keys don't have its key.
+fn validate<Claims: DeserializeOwned, const N: usize>(keys: &crate::keys::Keys<N>, previous: Option<&KeysSnapshot>, token: &str) -> Result<jsonwebtoken::TokenData<Claims>, crate::keys::ValidateError> {
+	match (keys.validate(token), previous) {
+		(Err(crate::keys::ValidateError::UnknownKey), Some(previous)) => previous.validate(token),
+		(result, _) => result,
+	}
+}
+
+/// Like [`validate`], but against a snapshot of the keys.
+fn validate_snapshot<Claims: DeserializeOwned>(keys: &KeysSnapshot, previous: Option<&KeysSnapshot>, token: &str) -> Result<jsonwebtoken::TokenData<Claims>, crate::keys::ValidateError> {
+	match (keys.validate(token), previous) {
+		(Err(crate::keys::ValidateError::UnknownKey), Some(previous)) => previous.validate(token),
+		(result, _) => result,
+	}
+}
+
+/// Validates the tokens across threads.
+fn validate_parallel<T: Send>(tokens: &[Box<str>], threads: usize, validate: impl Fn(&str) -> T + Sync) -> Vec<T> {
+	let validate = |tokens: &[Box<str>]| tokens.iter().map(|token| validate(token)).collect::<Vec<_>>();
+	std::thread::scope(|scope| {
+		let handles = tokens.chunks(tokens.len().div_ceil(threads))
+			.map(|chunk| scope.spawn(move || validate(chunk)))
+			.collect::<Vec<_>>();
+		handles.into_iter()
+			.flat_map(|handle| handle.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+			.collect()
+	})
+}
+
+/// A [`Keys`] cache that validates behind a shared reference, e.g. in an `Arc` across tasks.
+///
+/// Validations share a read lock, and only refreshes take the write lock.
+pub struct SharedKeys<INSTANT = std::time::SystemTime, const N: usize = { crate::keys::KEYS_CAPACITY }, FETCHER = Google>(tokio::sync::RwLock<Keys<INSTANT, N, FETCHER>>);
+
+impl<INSTANT, const N: usize, FETCHER: Default> Default for SharedKeys<INSTANT, N, FETCHER> {
+	fn default() -> Self { Keys::default().into() }
+}
+
+impl<INSTANT, const N: usize, FETCHER> From<Keys<INSTANT, N, FETCHER>> for SharedKeys<INSTANT, N, FETCHER> {
+	fn from(keys: Keys<INSTANT, N, FETCHER>) -> Self { Self(tokio::sync::RwLock::new(keys)) }
+}
+
+impl<INSTANT, const N: usize> SharedKeys<INSTANT, N> {
+	/// New empty cache.
+	pub const fn new() -> Self { Self(tokio::sync::RwLock::const_new(Keys::new())) }
+}
+
+impl<INSTANT, const N: usize, FETCHER> SharedKeys<INSTANT, N, FETCHER> {
+	/// Locks the cache for writing, e.g. to configure it.
+	pub async fn write(&self) -> tokio::sync::RwLockWriteGuard<'_, Keys<INSTANT, N, FETCHER>> { self.0.write().await }
+
+	/// Validates a token, like [`Keys::validate`].
+	pub async fn validate_shared<Claims: DeserializeOwned>(&self, token: &str) -> Result<jsonwebtoken::TokenData<Claims>, Error> where INSTANT: crate::fetch::Instant, FETCHER: KeyFetcher {
+		let keys = self.0.read().await;
+		let keys = if keys.is_valid() { keys } else {
+			drop(keys);
+			let mut keys = self.0.write().await;
+			// Another task may have refreshed while we waited for the lock, in which case this is a no-op.
+			keys.refresh_if_expired().await?;
+			keys.downgrade()
+		};
+		Ok(validate(&keys.keys, keys.previous(), token)?)
+	}
+}
+
+/// [`Keys`] caches of several sources, e.g. Google Accounts' and Firebase's keys.
+///
+/// Tokens are validated against the source that has their key.
+pub struct MultiKeys<INSTANT = std::time::SystemTime, const N: usize = { crate::keys::KEYS_CAPACITY }, FETCHER = Google> {
+	pub sources: Vec<Keys<INSTANT, N, FETCHER>>,
+}
+
+impl<INSTANT, const N: usize, FETCHER> From<Vec<Keys<INSTANT, N, FETCHER>>> for MultiKeys<INSTANT, N, FETCHER> {
+	fn from(sources: Vec<Keys<INSTANT, N, FETCHER>>) -> Self { Self { sources } }
+}
+
+impl<INSTANT, const N: usize, FETCHER> MultiKeys<INSTANT, N, FETCHER> {
+	/// [Refreshes](Keys::refresh) all the sources concurrently.
+	///
+	/// Returns the result of each source in order: a source that fails to refresh is left as it
+	/// was, and doesn't fail the others.
+	pub async fn refresh_all(&mut self) -> Vec<Result<(), crate::keys::FetchExtendError>> where INSTANT: crate::fetch::Instant, FETCHER: KeyFetcher {
+		futures_util::future::join_all(self.sources.iter_mut().map(Keys::refresh)).await
+	}
+
+	/// Validates a token against the first source that has its key, without refreshing.
+	pub fn validate<Claims: DeserializeOwned>(&self, token: &str) -> Result<jsonwebtoken::TokenData<Claims>, crate::keys::ValidateError> where INSTANT: crate::fetch::Instant {
+		for source in &self.sources {
+			match validate(&source.keys, source.previous(), token) {
+				Err(crate::keys::ValidateError::UnknownKey) => continue,
+				result => return result,
+			}
+		}
+		Err(crate::keys::ValidateError::UnknownKey)
+	}
+}
+
+/// A handle to the latest [`KeysSnapshot`] of a [`Keys`] cache.
+///
+/// See [`Keys::snapshots`].
+#[derive(Clone)]
+pub struct Snapshots(Arc<ArcSwap<KeysSnapshot>>);
+
+impl Snapshots {
+	/// Loads the latest snapshot.
+	pub fn load(&self) -> Arc<KeysSnapshot> { self.0.load_full() }
+}
+
 /// [`Keys::validate`] error.
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -50,4 +533,395 @@ pub enum Error {
 	FetchExtend(#[from] crate::keys::FetchExtendError),
 	#[error("failed to validate token: {0}")]
 	Validate(#[from] crate::keys::ValidateError),
+	#[cfg(feature = "blocking")]
+	#[error("failed to build the runtime to fetch keys on: {0}")]
+	Runtime(std::io::Error),
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::testing;
+
+	#[tokio::test]
+	async fn test_status() {
+		let mut cache = Keys::<testing::MockInstant>::new();
+		assert_eq!(cache.status(), Status { key_count: 0, expires_at: None, breaker: None });
+		cache.keys = testing::keys();
+		cache.expiration = MaybeUninit::new(testing::MockInstant(100));
+		assert_eq!(cache.status(), Status { key_count: 2, expires_at: Some(testing::MockInstant(100)), breaker: None });
+
+		testing::MockInstant::set(0);
+		let mut cache = Keys::<testing::MockInstant, { crate::keys::KEYS_CAPACITY }, _>::with_fetcher(|| std::future::ready(Err(ErrorFetch::Timeout)));
+		cache.set_breaker(Some(Breaker::new(1, 60, 60)));
+		assert_eq!(cache.status().breaker, Some(BreakerState::Closed));
+		assert!(cache.refresh().await.is_err());
+		assert_eq!(cache.status().breaker, Some(BreakerState::Open));
+	}
+
+	#[test]
+	fn test_debug() {
+		let mut cache = Keys::<testing::MockInstant>::new();
+		assert_eq!(format!("{cache:?}"), "Keys { keys: Keys { len: 0, keys: [] }, expiration: None, breaker: None, grace_period: 0, .. }");
+		cache.keys = testing::keys();
+		cache.expiration = MaybeUninit::new(testing::MockInstant(100));
+		assert!(format!("{cache:?}").starts_with(r#"Keys { keys: Keys { len: 2, keys: [("a", Rsa), ("b", Rsa)] }, expiration: Some(MockInstant(100)),"#));
+	}
+
+	#[tokio::test]
+	async fn test_persist_restore() {
+		let mut cache = Keys::<std::time::SystemTime, { crate::keys::KEYS_CAPACITY }, _>::with_fetcher(|| std::future::ready(response(0, 3600)));
+		assert_eq!(cache.persist(), None);
+		cache.refresh().await.unwrap();
+		let persisted = serde_json::to_string(&cache.persist().unwrap()).unwrap();
+
+		let mut restored = Keys::<std::time::SystemTime, { crate::keys::KEYS_CAPACITY }, _>::with_fetcher(|| -> std::future::Ready<Result<(crate::fetch::Age, Vec<u8>), ErrorFetch>> {
+			panic!("restored keys shouldn't be fetched")
+		});
+		restored.restore(serde_json::from_str(&persisted).unwrap()).unwrap();
+		assert_eq!(restored.status(), cache.status());
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &testing::claims(3600));
+		restored.validate::<serde_json::Value>(&token).await.unwrap();
+		assert_eq!(restored.persist(), cache.persist());
+	}
+
+	#[test]
+	fn test_is_valid_expiration() {
+		let mut cache = Keys::<testing::MockInstant>::new();
+		testing::MockInstant::set(0);
+		assert!(!cache.is_valid());
+		cache.keys = testing::keys();
+		cache.expiration = MaybeUninit::new(testing::MockInstant(100));
+		assert!(cache.is_valid());
+		testing::MockInstant::set(100);
+		assert!(cache.is_valid());
+		testing::MockInstant::set(101);
+		assert!(!cache.is_valid());
+	}
+
+	#[test]
+	fn test_capacity() {
+		let mut cache = Keys::<std::time::SystemTime, 1>::new();
+		assert!(cache.keys.push(b"a", testing::RSA_A_CERT.as_bytes()).unwrap());
+		assert!(!cache.keys.push(b"b", testing::RSA_A_CERT.as_bytes()).unwrap());
+	}
+
+	#[tokio::test]
+	async fn test_validate_batch() {
+		let valid = testing::sign(Some("a"), testing::RSA_A_KEY, &testing::claims(3600));
+		let unknown = testing::sign(Some("c"), testing::RSA_A_KEY, &testing::claims(3600));
+		let tokens = (0..100).map(|i| if i % 10 == 0 { unknown.as_str() } else { valid.as_str() }).collect::<Vec<_>>();
+
+		let fetches = std::sync::atomic::AtomicUsize::new(0);
+		let mut cache = Keys::<testing::MockInstant, { crate::keys::KEYS_CAPACITY }, _>::with_fetcher(|| {
+			fetches.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+			std::future::ready(response(0, 100))
+		});
+		testing::MockInstant::set(0);
+		// The cache starts empty, so the batch refreshes it, once, even across threads (on this
+		// current-thread runtime).
+		for threads in [1, 4] {
+			cache.keys.clear();
+			fetches.store(0, std::sync::atomic::Ordering::Relaxed);
+			let results = cache.validate_batch_on::<serde_json::Value>(&tokens, threads).await.unwrap();
+			assert_eq!(fetches.load(std::sync::atomic::Ordering::Relaxed), 1);
+			assert_eq!(results.len(), 100);
+			for (i, result) in results.iter().enumerate() {
+				if i % 10 == 0 {
+					assert!(matches!(result, Err(crate::keys::ValidateError::UnknownKey)));
+				} else {
+					assert!(result.is_ok());
+				}
+			}
+		}
+		cache.validate_batch::<serde_json::Value>(&tokens).await.unwrap();
+		assert_eq!(fetches.load(std::sync::atomic::Ordering::Relaxed), 1);
+	}
+
+	#[tokio::test]
+	async fn test_time_to_expiry() {
+		let mut cache = Keys::<testing::MockInstant, { crate::keys::KEYS_CAPACITY }, _>::with_fetcher(|| std::future::ready(response(10, 100)));
+		assert_eq!(cache.time_to_expiry(), None);
+		testing::MockInstant::set(0);
+		cache.refresh().await.unwrap();
+		assert_eq!(cache.time_to_expiry(), Some(Duration::from_secs(90)));
+		testing::MockInstant::set(60);
+		assert_eq!(cache.time_to_expiry(), Some(Duration::from_secs(30)));
+	}
+
+	#[tokio::test]
+	async fn test_ttl_bounds() {
+		let mut cache = Keys::<testing::MockInstant, { crate::keys::KEYS_CAPACITY }, _>::with_fetcher(|| std::future::ready(response(0, 1)));
+		cache.set_min_ttl(300);
+		testing::MockInstant::set(0);
+		cache.refresh().await.unwrap();
+		testing::MockInstant::set(300);
+		assert!(cache.is_valid());
+		testing::MockInstant::set(301);
+		assert!(!cache.is_valid());
+		assert_eq!(cache.time_to_expiry(), Some(Duration::ZERO));
+
+		let mut cache = Keys::<testing::MockInstant, { crate::keys::KEYS_CAPACITY }, _>::with_fetcher(|| std::future::ready(response(0, 1 << 40)));
+		cache.set_max_ttl(3600);
+		testing::MockInstant::set(0);
+		cache.refresh().await.unwrap();
+		assert_eq!(cache.status().expires_at, Some(testing::MockInstant(3600)));
+	}
+
+	#[tokio::test]
+	async fn test_grace_period() {
+		let retired = testing::sign(Some("a"), testing::RSA_A_KEY, &testing::claims(3600));
+		let mut cache = Keys::<testing::MockInstant, { crate::keys::KEYS_CAPACITY }, _>::with_fetcher(|| std::future::ready(response(0, 100)));
+		cache.set_grace_period(60);
+		testing::MockInstant::set(0);
+		cache.keys = testing::keys();
+
+		// A refresh that drops key "a".
+		testing::MockInstant::set(100);
+		cache.retire();
+		cache.keys.clear();
+		cache.keys.push(b"b", testing::RSA_B_CERT.as_bytes()).unwrap();
+		cache.expiration = MaybeUninit::new(testing::MockInstant(1000));
+
+		cache.validate::<serde_json::Value>(&retired).await.unwrap();
+		testing::MockInstant::set(160);
+		cache.validate::<serde_json::Value>(&retired).await.unwrap();
+		testing::MockInstant::set(161);
+		assert!(matches!(
+			cache.validate::<serde_json::Value>(&retired).await,
+			Err(Error::Validate(crate::keys::ValidateError::UnknownKey)),
+		));
+	}
+
+	#[tokio::test]
+	async fn test_validate_swr() {
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &testing::claims(3600));
+		let fetches = std::cell::Cell::new(0);
+		let mut cache = Keys::<testing::MockInstant, { crate::keys::KEYS_CAPACITY }, _>::with_fetcher(|| {
+			fetches.set(fetches.get() + 1);
+			std::future::ready(response(0, 100))
+		});
+		testing::MockInstant::set(0);
+		assert!(!cache.is_stale_usable(100));
+		cache.keys = testing::keys();
+		cache.expiration = MaybeUninit::new(testing::MockInstant(100));
+
+		testing::MockInstant::set(50);
+		cache.validate_swr::<serde_json::Value>(&token, 100).await.unwrap();
+		assert!(!cache.is_refresh_pending());
+
+		testing::MockInstant::set(150);
+		assert!(!cache.is_valid());
+		// Validated against the stale test keys, without refreshing them.
+		cache.validate_swr::<serde_json::Value>(&token, 100).await.unwrap();
+		assert!(cache.keys.contains(b"b"));
+		assert_eq!(fetches.get(), 0);
+		assert!(cache.is_refresh_pending());
+		testing::MockInstant::set(200);
+		assert!(cache.is_stale_usable(100));
+		testing::MockInstant::set(201);
+		assert!(!cache.is_stale_usable(100));
+
+		testing::MockInstant::set(150);
+		cache.refresh_if_pending().await.unwrap();
+		assert_eq!(fetches.get(), 1);
+		assert!(cache.is_valid() && !cache.keys.contains(b"b"));
+		assert!(!cache.is_refresh_pending());
+		cache.refresh_if_pending().await.unwrap();
+		assert_eq!(fetches.get(), 1);
+	}
+
+	#[tokio::test]
+	async fn test_validate_shared() {
+		let valid = testing::sign(Some("a"), testing::RSA_A_KEY, &testing::claims(3600));
+		let unknown = testing::sign(Some("c"), testing::RSA_A_KEY, &testing::claims(3600));
+		testing::MockInstant::set(0);
+		let mut cache = Keys::<testing::MockInstant, { crate::keys::KEYS_CAPACITY }, _>::with_fetcher(|| std::future::ready(response(0, 100)));
+		cache.keys = testing::keys();
+		cache.expiration = MaybeUninit::new(testing::MockInstant(100));
+		let cache = Arc::new(SharedKeys::from(cache));
+
+		let tasks = (0..16).map(|i| {
+			let cache = cache.clone();
+			let token = if i % 4 == 0 { unknown.clone() } else { valid.clone() };
+			tokio::spawn(async move { cache.validate_shared::<serde_json::Value>(&token).await.is_ok() })
+		}).collect::<Vec<_>>();
+		for (i, task) in tasks.into_iter().enumerate() {
+			assert_eq!(task.await.unwrap(), i % 4 != 0);
+		}
+	}
+
+	#[cfg(feature = "blocking")]
+	#[test]
+	fn test_validate_blocking() {
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &testing::claims(3600));
+		testing::MockInstant::set(0);
+		let mut cache = Keys::<testing::MockInstant>::new();
+		cache.keys = testing::keys();
+		cache.expiration = MaybeUninit::new(testing::MockInstant(100));
+		cache.validate_blocking::<serde_json::Value>(&token).unwrap();
+		let unknown = testing::sign(Some("c"), testing::RSA_A_KEY, &testing::claims(3600));
+		assert!(matches!(
+			cache.validate_blocking::<serde_json::Value>(&unknown),
+			Err(Error::Validate(crate::keys::ValidateError::UnknownKey)),
+		));
+	}
+
+	/// The body of a certs response with the key "a", and its age.
+	#[tokio::test]
+	async fn test_refresh_all() {
+		// Each fetch waits for the other, so only concurrent fetches get past it.
+		let barrier = Arc::new(tokio::sync::Barrier::new(2));
+		let source = |ok: bool| {
+			let barrier = barrier.clone();
+			Keys::<testing::MockInstant, { crate::keys::KEYS_CAPACITY }, _>::with_fetcher(move || {
+				let barrier = barrier.clone();
+				async move {
+					barrier.wait().await;
+					if ok { response(0, 100) } else { Err(ErrorFetch::Other("offline".into())) }
+				}
+			})
+		};
+		let mut keys = MultiKeys::from(vec![source(false), source(true)]);
+		testing::MockInstant::set(0);
+		let results = tokio::time::timeout(Duration::from_secs(5), keys.refresh_all()).await.unwrap();
+		assert!(matches!(results[0], Err(crate::keys::FetchExtendError::Fetch(ErrorFetch::Other(_)))));
+		assert!(results[1].is_ok());
+		assert!(keys.sources[0].keys.is_empty());
+
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &testing::claims(3600));
+		keys.validate::<serde_json::Value>(&token).unwrap();
+		let unknown = testing::sign(Some("c"), testing::RSA_A_KEY, &testing::claims(3600));
+		assert!(matches!(keys.validate::<serde_json::Value>(&unknown), Err(crate::keys::ValidateError::UnknownKey)));
+	}
+
+	fn response(age: u64, max_age: u64) -> Result<(crate::fetch::Age, Vec<u8>), ErrorFetch> {
+		Ok((crate::fetch::Age { age, max_age }, testing::certs_body([("a", testing::RSA_A_CERT)])))
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn test_refresh_on_expiry() {
+		let fetches = std::cell::Cell::new(0);
+		let mut cache = Keys::<tokio::time::Instant, { crate::keys::KEYS_CAPACITY }, _>::with_fetcher(|| {
+			fetches.set(fetches.get() + 1);
+			std::future::ready(response(10, 100))
+		});
+
+		cache.refresh_with(Retry::NONE, tokio::time::sleep).await.unwrap();
+		assert_eq!(fetches.get(), 1);
+		tokio::time::advance(Duration::from_secs(90)).await;
+		cache.refresh_with(Retry::NONE, tokio::time::sleep).await.unwrap();
+		assert_eq!(fetches.get(), 1);
+		tokio::time::advance(Duration::from_secs(1)).await;
+		assert!(!cache.is_valid());
+		cache.refresh_with(Retry::NONE, tokio::time::sleep).await.unwrap();
+		assert_eq!(fetches.get(), 2);
+	}
+
+	#[tokio::test]
+	async fn test_refresh_retry() {
+		let retry = Retry { retries: 3, base_delay: Duration::from_millis(100) };
+		let attempts = std::cell::Cell::new(0);
+		let sleeps = std::cell::RefCell::new(Vec::new());
+		let sleep = |delay| {
+			sleeps.borrow_mut().push(delay);
+			std::future::ready(())
+		};
+		testing::MockInstant::set(0);
+
+		// Succeeds on the third attempt.
+		let mut cache = Keys::<testing::MockInstant, { crate::keys::KEYS_CAPACITY }, _>::with_fetcher(|| {
+			attempts.set(attempts.get() + 1);
+			std::future::ready(if attempts.get() < 3 { Err(ErrorFetch::Timeout) } else { response(0, 100) })
+		});
+		cache.refresh_with(retry, sleep).await.unwrap();
+		assert_eq!(attempts.get(), 3);
+		assert_eq!(*sleeps.borrow(), [Duration::from_millis(100), Duration::from_millis(200)]);
+		assert!(cache.keys.contains(b"a") && cache.is_valid());
+
+		// Gives up after the retries, with the fetch error.
+		attempts.set(0);
+		sleeps.borrow_mut().clear();
+		let mut cache = Keys::<testing::MockInstant, { crate::keys::KEYS_CAPACITY }, _>::with_fetcher(|| {
+			attempts.set(attempts.get() + 1);
+			std::future::ready(Err(ErrorFetch::Timeout))
+		});
+		assert!(matches!(
+			cache.refresh_with(retry, sleep).await,
+			Err(crate::keys::FetchExtendError::Fetch(ErrorFetch::Timeout)),
+		));
+		assert_eq!(attempts.get(), 4);
+		assert_eq!(*sleeps.borrow(), [100, 200, 400].map(Duration::from_millis));
+	}
+
+	#[tokio::test]
+	async fn test_refresh_failure_keeps_keys() {
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &testing::claims(3600));
+		let sleep = |_| std::future::ready(());
+		let responses = std::cell::RefCell::new(std::collections::VecDeque::from([
+			response(0, 100),
+			Err(ErrorFetch::Timeout),
+			Err(ErrorFetch::Other("offline".into())),
+			Err(ErrorFetch::Process(crate::fetch::ErrorProcess::Status(503))),
+			Ok((crate::fetch::Age { age: 0, max_age: 100 }, testing::certs_body([("a", "not a pem")]))),
+		]));
+		testing::MockInstant::set(0);
+		let mut cache = Keys::<testing::MockInstant, { crate::keys::KEYS_CAPACITY }, _>::with_fetcher(|| {
+			std::future::ready(responses.borrow_mut().pop_front().unwrap())
+		});
+		cache.refresh_with(Retry::NONE, sleep).await.unwrap();
+
+		testing::MockInstant::set(101);
+		while !responses.borrow().is_empty() {
+			assert!(cache.refresh_with(Retry::NONE, sleep).await.is_err());
+			assert!(cache.keys.contains(b"a"));
+			cache.validate_swr::<serde_json::Value>(&token, 60).await.unwrap();
+		}
+	}
+
+	#[tokio::test]
+	async fn test_on_refresh() {
+		let sleep = |_| std::future::ready(());
+		testing::MockInstant::set(0);
+		let mut cache = Keys::<testing::MockInstant, { crate::keys::KEYS_CAPACITY }, _>::with_fetcher(|| std::future::ready(response(0, 100)));
+		let mut refreshes = cache.on_refresh();
+		assert_eq!(*refreshes.borrow_and_update(), Status { key_count: 0, expires_at: None, breaker: None });
+
+		cache.refresh_with(Retry::NONE, sleep).await.unwrap();
+		assert!(refreshes.has_changed().unwrap());
+		assert_eq!(*refreshes.borrow_and_update(), Status { key_count: 1, expires_at: Some(testing::MockInstant(100)), breaker: None });
+
+		// Valid keys aren't refreshed, unless explicitly.
+		cache.refresh_with(Retry::NONE, sleep).await.unwrap();
+		assert!(!refreshes.has_changed().unwrap());
+		cache.refresh().await.unwrap();
+		assert!(refreshes.has_changed().unwrap());
+	}
+
+	#[test]
+	fn test_snapshots_swap() {
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &testing::claims(3600));
+		let mut cache = Keys::<std::time::SystemTime>::new();
+		let snapshots = cache.snapshots();
+		assert!(snapshots.load().is_empty());
+		cache.keys = testing::keys();
+		cache.publish();
+
+		std::thread::scope(|scope| {
+			for _ in 0..4 {
+				let snapshots = snapshots.clone();
+				let token = &token;
+				scope.spawn(move || {
+					for _ in 0..50 {
+						snapshots.load().validate::<serde_json::Value>(token).unwrap();
+					}
+				});
+			}
+			for _ in 0..50 {
+				cache.keys = testing::keys();
+				cache.publish();
+			}
+		});
+		assert_eq!(snapshots.load().len(), 2);
+	}
 }
@@ -0,0 +1,164 @@
+//! [OpenID Connect discovery](https://openid.net/specs/openid-connect-discovery-1_0.html) of
+//! Google's keys and issuer.
+//!
+//! Rather than relying on [`fetch::HOST`](crate::fetch::HOST) and [`fetch::JWK_PATH`](crate::fetch::JWK_PATH),
+//! [`fetch_and_build`] reads them (and the issuer) from Google's discovery document.
+
+use tokio_rustls::TlsConnector;
+
+use crate::{fetch::{Age, ErrorFetch, ErrorProcess}, keys::{Keys, ValidateError, ValidationBuilder, KEYS_CAPACITY}};
+
+/// The host of Google's discovery document.
+pub const HOST: &str = "accounts.google.com";
+/// The path of Google's discovery document.
+pub const PATH: &str = "/.well-known/openid-configuration";
+
+/// Keys and validation from a discovery document.
+pub struct Discovered<const N: usize = KEYS_CAPACITY> {
+	/// The keys of the document's `jwks_uri`.
+	pub keys: Keys<N>,
+	/// The document's `issuer`.
+	pub issuer: String,
+	/// The expiration data of the keys.
+	pub age: Age,
+	/// The validation of tokens by the issuer.
+	validation: ValidationBuilder,
+}
+
+impl<const N: usize> Discovered<N> {
+	/// Gets the validation of tokens by the [issuer](Self::issuer).
+	pub fn validation(&self) -> &ValidationBuilder { &self.validation }
+
+	/// Validates a token against the keys, requiring it to be by the [issuer](Self::issuer).
+	///
+	/// Google's `https://accounts.google.com` issuer is also accepted without its scheme, as Google
+	/// issues tokens with either.
+	pub fn validate<Claims: serde::de::DeserializeOwned>(&self, token: &str) -> Result<jsonwebtoken::TokenData<Claims>, ValidateError> {
+		self.keys.validate_with_builder(token, &self.validation)
+	}
+}
+
+/// Fetches Google's discovery document, then the keys it points to.
+pub async fn fetch_and_build<const N: usize>() -> Result<Discovered<N>, Error> {
+	fetch_and_build_with(&crate::fetch::CONNECTOR, HOST, PATH).await
+}
+
+/// Like [`fetch_and_build`], but with the given TLS connector, from the given host and path.
+///
+/// The host may have a port (e.g. `127.0.0.1:8443`), otherwise it's 443.
+pub async fn fetch_and_build_with<const N: usize>(connector: &TlsConnector, host: &str, path: &str) -> Result<Discovered<N>, Error> {
+	let mut buffer = Vec::new();
+	crate::fetch::into_vec_from_with(connector, host, path, &mut buffer).await?;
+	let document = document(&mut buffer)?;
+	let (host, path) = split_https(&document.jwks_uri).ok_or(Error::JwksUri)?;
+
+	crate::fetch::into_vec_from_with(connector, host, path, &mut buffer).await?;
+	let mut keys = Keys::new();
+	let (all_fit, age) = keys.extend_jwk_response(&mut buffer)?;
+	if !all_fit { return Err(Error::Capacity); }
+	let validation = ValidationBuilder::new().issuers(&issuers(&document.issuer));
+	Ok(Discovered { keys, issuer: document.issuer, age, validation })
+}
+
+/// The fields of a discovery document that locate and check the keys.
+#[derive(Debug, serde::Deserialize)]
+struct Document {
+	issuer: String,
+	jwks_uri: String,
+}
+
+/// Gets the document of a discovery response.
+fn document(response: &mut [u8]) -> Result<Document, Error> {
+	let status = crate::fetch::status(response).ok_or(ErrorProcess::StatusLine)?;
+	if !(200..300).contains(&status) { return Err(ErrorProcess::Status(status).into()); }
+	let body = crate::fetch::body(response).ok_or(ErrorProcess::Body)?;
+	let body = if crate::fetch::is_chunked(response) {
+		let len = crate::fetch::dechunk(&mut response[body..]);
+		&response[body..body + len]
+	} else {
+		&response[body..]
+	};
+	Ok(serde_json::from_slice(body)?)
+}
+
+/// Gets the accepted forms of a token issuer.
+fn issuers(issuer: &str) -> Vec<&str> {
+	match issuer {
+		"https://accounts.google.com" => vec![issuer, "accounts.google.com"],
+		_ => vec![issuer],
+	}
+}
+
+/// Splits an `https` URI into its host and path.
+fn split_https(uri: &str) -> Option<(&str, &str)> {
+	let uri = uri.strip_prefix("https://")?;
+	let (host, path) = uri.find('/').map_or((uri, "/"), |slash| uri.split_at(slash));
+	(!host.is_empty()).then_some((host, path))
+}
+
+/// [`fetch_and_build`] error.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+	#[error("fetch error: {0}")]
+	Fetch(#[from] ErrorFetch),
+	#[error("HTTP process error: {0}")]
+	HttpProcess(#[from] ErrorProcess),
+	#[error("malformed discovery document: {0}")]
+	Document(#[from] serde_json::Error),
+	#[error("the discovery document's jwks_uri isn't an https URI")]
+	JwksUri,
+	#[error("failed to load the keys: {0}")]
+	Keys(#[from] crate::keys::FetchExtendError),
+	#[error("there's no room for all the keys")]
+	Capacity,
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_document() {
+		let mut response = concat!(
+			"HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n",
+			r#"{"issuer": "https://accounts.google.com", "jwks_uri": "https://www.googleapis.com/oauth2/v3/certs", "id_token_signing_alg_values_supported": ["RS256"]}"#,
+		).as_bytes().to_vec();
+		let parsed = document(&mut response).unwrap();
+		assert_eq!(parsed.issuer, "https://accounts.google.com");
+		assert_eq!(split_https(&parsed.jwks_uri), Some(("www.googleapis.com", "/oauth2/v3/certs")));
+
+		let mut response = b"HTTP/1.0 404 Not Found\r\n\r\n".to_vec();
+		assert!(matches!(document(&mut response), Err(Error::HttpProcess(ErrorProcess::Status(404)))));
+	}
+
+	#[test]
+	fn test_issuers() {
+		let discovered = |issuer: &str| Discovered::<KEYS_CAPACITY> {
+			keys: crate::testing::keys(),
+			issuer: issuer.to_owned(),
+			age: Age::default(),
+			validation: ValidationBuilder::new().issuers(&issuers(issuer)),
+		};
+		let token = |iss: &str| {
+			let mut claims = crate::testing::claims(3600);
+			claims["iss"] = iss.into();
+			crate::testing::sign(Some("a"), crate::testing::RSA_A_KEY, &claims)
+		};
+		let google = discovered("https://accounts.google.com");
+		google.validate::<serde_json::Value>(&token("https://accounts.google.com")).unwrap();
+		google.validate::<serde_json::Value>(&token("accounts.google.com")).unwrap();
+		assert!(google.validate::<serde_json::Value>(&token("https://example.com")).is_err());
+
+		let other = discovered("https://example.com");
+		other.validate::<serde_json::Value>(&token("https://example.com")).unwrap();
+		assert!(other.validate::<serde_json::Value>(&token("example.com")).is_err());
+	}
+
+	#[test]
+	fn test_split_https() {
+		assert_eq!(split_https("https://127.0.0.1:8443/keys"), Some(("127.0.0.1:8443", "/keys")));
+		assert_eq!(split_https("https://example.com"), Some(("example.com", "/")));
+		assert_eq!(split_https("http://example.com/keys"), None);
+		assert_eq!(split_https("https:///keys"), None);
+	}
+}
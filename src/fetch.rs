@@ -1,24 +1,57 @@
 //! [`fetch::into`](into) and HTTP processing.
+//!
+//! The network half of this module requires the `net` feature; the HTTP processing half doesn't.
 
-use std::{sync::Arc, time::SystemTime};
+use std::time::SystemTime;
+#[cfg(feature = "net")]
+use std::{sync::Arc, time::Duration};
 
+#[cfg(feature = "net")]
 use once_cell::sync::Lazy;
+#[cfg(feature = "net")]
 use rustls::pki_types::ServerName;
-use tokio::{net::TcpStream, io::{AsyncWriteExt, AsyncReadExt}};
+#[cfg(feature = "net")]
+use tokio::{net::TcpStream, io::{AsyncRead, AsyncWrite, AsyncWriteExt, AsyncReadExt}};
+#[cfg(feature = "net")]
 use tokio_rustls::{rustls, TlsConnector};
 
 /// TLS connector.
-static CONNECTOR: Lazy<TlsConnector> = Lazy::new(|| {
+#[cfg(feature = "net")]
+pub(crate) static CONNECTOR: Lazy<TlsConnector> = Lazy::new(|| {
+	connector(Arc::new(rustls::client::ClientSessionMemoryCache::new(SESSION_CACHE_SIZE)))
+});
+
+/// The number of TLS sessions the default connector remembers to resume handshakes with.
+#[cfg(feature = "net")]
+pub const SESSION_CACHE_SIZE: usize = 32;
+
+/// Creates a TLS connector that resumes handshakes from the given session storage.
+///
+/// Fetches with the same connector (see [`into_from_with`]) share its sessions, so repeated
+/// refreshes to the same host skip most of the handshake.
+#[cfg(feature = "net")]
+pub fn connector(sessions: Arc<dyn rustls::client::ClientSessionStore>) -> TlsConnector {
 	let mut root_store = rustls::RootCertStore::empty();
 	root_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
-	let client_config = rustls::ClientConfig::builder()
-		.with_root_certificates(root_store)
+	connector_with_roots(root_store, sessions)
+}
+
+/// Creates a TLS connector that trusts the roots and resumes handshakes from the session storage.
+#[cfg(feature = "net")]
+fn connector_with_roots(roots: rustls::RootCertStore, sessions: Arc<dyn rustls::client::ClientSessionStore>) -> TlsConnector {
+	let mut client_config = rustls::ClientConfig::builder()
+		.with_root_certificates(roots)
 		.with_no_client_auth();
+	client_config.resumption = rustls::client::Resumption::store(sessions);
 	TlsConnector::from(Arc::new(client_config))
-});
+}
 
-/// The Google API server name.
-static SERVER_NAME: Lazy<ServerName<'static>> = Lazy::new(|| "googleapis.com".try_into().expect("invalid DNS name"));
+/// The Google API host.
+///
+/// Fetches connect to it, and use it for both the TLS server name and the `Host` header, so they
+/// can't disagree.
+#[cfg(feature = "net")]
+pub const HOST: &str = "www.googleapis.com";
 
 /// Fetches an HTTP PEM response into the given buffer and returns the number of bytes written.
 ///
@@ -26,28 +59,616 @@ static SERVER_NAME: Lazy<ServerName<'static>> = Lazy::new(|| "googleapis.com".tr
 /// time for the keys, or if you don't care you can just get the [`body`].
 ///
 /// Then you might want to [`parse`](crate::parse()) it.
+///
+/// Fails with [`ErrorFetch::BufferFull`] if the response doesn't fit in the buffer; see
+/// [`into_vec`] for a growable alternative. Buffers shorter than [`MIN_BUFFER_LEN`] fail right
+/// away with [`ErrorFetch::BufferTooSmall`].
+///
+/// Times out after [`DEFAULT_TIMEOUT`]; see [`into_timeout`].
+#[cfg(feature = "net")]
 pub async fn into(buffer: &mut [u8]) -> Result<usize, ErrorFetch> {
-	let stream = TcpStream::connect("googleapis.com:443").await.map_err(ErrorFetch::Connect)?;
-	let mut stream = CONNECTOR.connect(SERVER_NAME.clone(), stream).await.map_err(ErrorFetch::ConnectTcp)?;
-	const REQUEST: &[u8] = b"GET /oauth2/v1/certs HTTP/1.0\r\nHost: www.googleapis.com\r\n\r\n";
-	stream.write(REQUEST).await.map_err(ErrorFetch::RequestWrite)?;
+	into_timeout(buffer, DEFAULT_TIMEOUT).await
+}
+
+/// The minimum length of the buffers fetches read into, which holds Google's headers (about 600
+/// bytes) with room to spare.
+#[cfg(feature = "net")]
+pub const MIN_BUFFER_LEN: usize = 1 << 10;
+
+/// The maximum length of the responses fetches read into a growing vector, e.g. [`into_vec`],
+/// which is far more than Google's (about 5 KiB) but keeps a hostile server (e.g. a discovered
+/// `jwks_uri`) from exhausting memory.
+#[cfg(feature = "net")]
+pub const MAX_RESPONSE_LEN: usize = 256 << 10;
+
+/// Fails with [`ErrorFetch::BufferTooSmall`] if the buffer is shorter than [`MIN_BUFFER_LEN`].
+#[cfg(feature = "net")]
+fn check_buffer(buffer: &[u8]) -> Result<(), ErrorFetch> {
+	if buffer.len() < MIN_BUFFER_LEN { return Err(ErrorFetch::BufferTooSmall); }
+	Ok(())
+}
+
+/// The timeout of fetches that don't specify one.
+#[cfg(feature = "net")]
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Like [`into`], but times out after the given duration, failing with [`ErrorFetch::Timeout`].
+///
+/// The timeout covers the whole fetch: connecting, requesting, and reading the response. On a
+/// timeout the connection is dropped, and the buffer may hold part of the response.
+#[cfg(feature = "net")]
+pub async fn into_timeout(buffer: &mut [u8], timeout: Duration) -> Result<usize, ErrorFetch> {
+	check_buffer(buffer)?;
+	traced(with_timeout(timeout, async { exchange(&mut connect(&CONNECTOR, HOST).await?, HOST, PEM_PATH, buffer).await })).await
+}
+
+/// Runs a fetch of Google's PEM endpoint in a `fetch` span (with the `tracing` feature), with an
+/// event of its byte count or error.
+#[cfg(feature = "net")]
+async fn traced(fetch: impl std::future::Future<Output = Result<usize, ErrorFetch>>) -> Result<usize, ErrorFetch> {
+	#[cfg(feature = "tracing")]
+	{
+		use tracing::Instrument;
+		async {
+			let result = fetch.await;
+			match &result {
+				Ok(bytes) => tracing::debug!(bytes, "fetched the keys"),
+				Err(error) => tracing::warn!(%error, "failed to fetch the keys"),
+			}
+			result
+		}.instrument(tracing::debug_span!("fetch", host = HOST, path = PEM_PATH)).await
+	}
+	#[cfg(not(feature = "tracing"))]
+	fetch.await
+}
+
+/// Like [`into`], but also [processes the headers](process_headers) of the response.
+///
+/// Returns the response length, its keys expiration data, and the [`body`] index, which is for
+/// [`parse`](crate::parse()) within the response (`buffer[body..len]`).
+#[cfg(feature = "net")]
+pub async fn into_with_age(buffer: &mut [u8]) -> Result<(usize, Age, usize), ErrorFetch> {
+	let len = into(buffer).await?;
+	with_age(buffer, len)
+}
+
+/// Processes the headers of a response of the given length in the buffer.
+#[cfg(feature = "net")]
+fn with_age(buffer: &[u8], len: usize) -> Result<(usize, Age, usize), ErrorFetch> {
+	let (age, body) = process_headers(&buffer[..len])?;
+	#[cfg(feature = "tracing")]
+	tracing::debug!(age = age.age, max_age = age.max_age, "processed the response headers");
+	Ok((len, age, body))
+}
+
+/// Like [`into`], but over the given stream rather than a connection of its own.
+///
+/// This only does the HTTP exchange, so the stream should already be connected to [`HOST`], and
+/// secured: it's for connecting some other way, e.g. through a proxy, with custom DNS resolution,
+/// or with a pinned certificate.
+#[cfg(feature = "net")]
+pub async fn over<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, buffer: &mut [u8]) -> Result<usize, ErrorFetch> {
+	check_buffer(buffer)?;
+	with_timeout(DEFAULT_TIMEOUT, exchange(&mut stream, HOST, PEM_PATH, buffer)).await
+}
+
+/// Like [`into`], but from the given host and path rather than Google's PEM endpoint.
+///
+/// The host may have a port (e.g. `127.0.0.1:8443`), otherwise it's 443.
+#[cfg(feature = "net")]
+pub async fn into_from(host: &str, path: &str, buffer: &mut [u8]) -> Result<usize, ErrorFetch> {
+	into_from_with(&CONNECTOR, host, path, buffer).await
+}
+
+/// Like [`into_from`], but with the given TLS [`connector`].
+#[cfg(feature = "net")]
+pub async fn into_from_with(connector: &TlsConnector, host: &str, path: &str, buffer: &mut [u8]) -> Result<usize, ErrorFetch> {
+	check_buffer(buffer)?;
+	with_timeout(DEFAULT_TIMEOUT, async { read_into(&mut request(connector, host, path).await?, buffer).await }).await
+}
+
+/// Like [`into_from_with`], but into a vector that's cleared and then grown as needed.
+#[cfg(feature = "net")]
+pub(crate) async fn into_vec_from_with(connector: &TlsConnector, host: &str, path: &str, buffer: &mut Vec<u8>) -> Result<usize, ErrorFetch> {
+	with_timeout(DEFAULT_TIMEOUT, async { read_into_vec(&mut request(connector, host, path).await?, buffer).await }).await
+}
+
+/// Creates a [`Fetcher`] that connects with the given TLS configuration, e.g. one that trusts only
+/// pinned roots rather than the whole webpki bundle.
+#[cfg(feature = "net")]
+pub fn with_config(config: Arc<rustls::ClientConfig>) -> Fetcher {
+	Fetcher { connector: TlsConnector::from(config) }
+}
+
+/// A fetcher that keeps its connection open between fetches (HTTP/1.1 keep-alive), so that
+/// periodic refreshes skip the TCP and TLS handshakes.
+///
+/// It reconnects when the server has closed the connection, e.g. after it idled (the request is
+/// then retried once on a new connection), and after responses that ask to close it or that are
+/// neither chunked nor have a `Content-Length`.
+#[cfg(feature = "net")]
+pub struct Persistent {
+	connector: TlsConnector,
+	host: String,
+	path: String,
+	/// The open connection, if any.
+	connection: tokio::sync::Mutex<Option<tokio_rustls::client::TlsStream<TcpStream>>>,
+}
+
+#[cfg(feature = "net")]
+impl Default for Persistent {
+	fn default() -> Self { Self::new() }
+}
+
+#[cfg(feature = "net")]
+impl Persistent {
+	/// Creates a persistent fetcher of Google's PEM endpoint.
+	pub fn new() -> Self { Self::with(CONNECTOR.clone(), HOST, PEM_PATH) }
+
+	/// Creates a persistent fetcher of the path on the host, with the given TLS connector.
+	///
+	/// The host may have a port (e.g. `127.0.0.1:8443`), otherwise it's 443.
+	pub fn with(connector: TlsConnector, host: &str, path: &str) -> Self {
+		Self { connector, host: host.to_owned(), path: path.to_owned(), connection: tokio::sync::Mutex::new(None) }
+	}
+
+	/// Fetches an HTTP response into the cleared vector and returns the number of bytes written,
+	/// over the open connection if there's one.
+	pub async fn into_vec(&self, buffer: &mut Vec<u8>) -> Result<usize, ErrorFetch> {
+		let mut connection = self.connection.lock().await;
+		let result = with_timeout(DEFAULT_TIMEOUT, self.exchange(&mut connection, buffer)).await;
+		// The connection may be mid-response.
+		if result.is_err() { *connection = None; }
+		result
+	}
+
+	/// Exchanges a request and response over the open connection, or else over a new one.
+	async fn exchange(&self, connection: &mut Option<tokio_rustls::client::TlsStream<TcpStream>>, buffer: &mut Vec<u8>) -> Result<usize, ErrorFetch> {
+		if let Some(stream) = connection.as_mut() {
+			match exchange_keep_alive(stream, &self.host, &self.path, buffer).await {
+				Ok(keep_alive) => {
+					if !keep_alive { *connection = None; }
+					return Ok(buffer.len());
+				}
+				// Nothing came back, so the server had closed the connection.
+				Err(_) if buffer.is_empty() => *connection = None,
+				Err(e) => return Err(e),
+			}
+		}
+		let stream = connection.insert(connect(&self.connector, &self.host).await?);
+		if !exchange_keep_alive(stream, &self.host, &self.path, buffer).await? { *connection = None; }
+		Ok(buffer.len())
+	}
+}
+
+#[cfg(feature = "net")]
+impl KeyFetcher for Persistent {
+	async fn fetch(&self) -> Result<(Age, Vec<u8>), ErrorFetch> {
+		let mut response = Vec::new();
+		self.into_vec(&mut response).await?;
+		split_body(response)
+	}
+}
+
+/// Sends an HTTP/1.1 request for the path over the stream, and reads the response into the
+/// cleared vector.
+///
+/// Returns whether the connection can be reused.
+#[cfg(feature = "net")]
+async fn exchange_keep_alive<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, host: &str, path: &str, buffer: &mut Vec<u8>) -> Result<bool, ErrorFetch> {
+	buffer.clear();
+	let request = format!("GET {path} HTTP/1.1\r\nHost: {host}\r\n\r\n");
+	stream.write_all(request.as_bytes()).await.map_err(ErrorFetch::RequestWrite)?;
+	stream.flush().await.map_err(ErrorFetch::RequestWrite)?;
+	loop {
+		if is_framed(buffer) && is_complete(buffer) { return Ok(is_keep_alive(buffer)); }
+		match stream.read_buf(buffer).await {
+			Ok(0) => break,
+			Ok(_) => check_response_len(buffer)?,
+			Err(e) if is_clean_eof(&e, buffer) => break,
+			Err(e) => return Err(ErrorFetch::Read(e)),
+		}
+	}
+	// The server closed the connection.
+	if buffer.is_empty() || !is_complete(buffer) { return Err(ErrorFetch::Read(std::io::ErrorKind::UnexpectedEof.into())); }
+	Ok(false)
+}
+
+/// Fetches with its own TLS configuration; see [`with_config`].
+#[cfg(feature = "net")]
+#[derive(Clone)]
+pub struct Fetcher {
+	connector: TlsConnector,
+}
+
+#[cfg(feature = "net")]
+impl Fetcher {
+	/// Like [`fetch::into`](into).
+	pub async fn into(&self, buffer: &mut [u8]) -> Result<usize, ErrorFetch> {
+		self.into_from(HOST, PEM_PATH, buffer).await
+	}
+
+	/// Like [`fetch::into_from`](into_from).
+	pub async fn into_from(&self, host: &str, path: &str, buffer: &mut [u8]) -> Result<usize, ErrorFetch> {
+		into_from_with(&self.connector, host, path, buffer).await
+	}
+}
+
+/// Fetches an HTTP JWK response into the given buffer and returns the number of bytes written.
+///
+/// Like [`into`], but for the JWK endpoint, whose body is for [`ParseJwk`](crate::ParseJwk).
+#[cfg(feature = "net")]
+pub async fn into_jwk(buffer: &mut [u8]) -> Result<usize, ErrorFetch> {
+	check_buffer(buffer)?;
+	with_timeout(DEFAULT_TIMEOUT, async { read_into(&mut request(&CONNECTOR, HOST, JWK_PATH).await?, buffer).await }).await
+}
+
+/// Fetches an HTTP PEM response into the given vector and returns the number of bytes written.
+///
+/// Like [`into`], but the vector is cleared and then grown as needed to hold the whole
+/// response, up to [`MAX_RESPONSE_LEN`].
+#[cfg(feature = "net")]
+pub async fn into_vec(buffer: &mut Vec<u8>) -> Result<usize, ErrorFetch> {
+	traced(with_timeout(DEFAULT_TIMEOUT, async { read_into_vec(&mut request(&CONNECTOR, HOST, PEM_PATH).await?, buffer).await })).await
+}
+
+/// Fetches an HTTP JWK response into the given vector and returns the number of bytes written.
+///
+/// Like [`into_jwk`], but the vector is cleared and then grown as needed to hold the whole
+/// response, up to [`MAX_RESPONSE_LEN`].
+#[cfg(feature = "net")]
+pub async fn into_jwk_vec(buffer: &mut Vec<u8>) -> Result<usize, ErrorFetch> {
+	with_timeout(DEFAULT_TIMEOUT, async { read_into_vec(&mut request(&CONNECTOR, HOST, JWK_PATH).await?, buffer).await }).await
+}
+
+/// Fails the fetch with [`ErrorFetch::Timeout`] if it doesn't complete in time.
+#[cfg(feature = "net")]
+async fn with_timeout<T>(timeout: Duration, fetch: impl std::future::Future<Output = Result<T, ErrorFetch>>) -> Result<T, ErrorFetch> {
+	tokio::time::timeout(timeout, fetch).await.map_err(|_| ErrorFetch::Timeout)?
+}
+
+/// The path of the PEM (X.509 certificates) endpoint.
+#[cfg(feature = "net")]
+pub const PEM_PATH: &str = "/oauth2/v1/certs";
+/// The path of the JWK endpoint.
+#[cfg(feature = "net")]
+pub const JWK_PATH: &str = "/oauth2/v3/certs";
+
+/// Connects to the host and sends a request for the path.
+#[cfg(feature = "net")]
+async fn request(connector: &TlsConnector, host: &str, path: &str) -> Result<tokio_rustls::client::TlsStream<TcpStream>, ErrorFetch> {
+	let mut stream = connect(connector, host).await?;
+	write_request(&mut stream, host, path).await?;
+	Ok(stream)
+}
+
+/// Connects to the host.
+#[cfg(feature = "net")]
+async fn connect(connector: &TlsConnector, host: &str) -> Result<tokio_rustls::client::TlsStream<TcpStream>, ErrorFetch> {
+	let (name, port) = match host.rsplit_once(':') {
+		Some((name, port)) => (name, port.parse().map_err(|_| ErrorFetch::InvalidHost)?),
+		None => (host, 443u16),
+	};
+	let server_name = ServerName::try_from(name.to_owned()).map_err(|_| ErrorFetch::InvalidHost)?;
+	let stream = TcpStream::connect((name, port)).await.map_err(ErrorFetch::Connect)?;
+	connector.connect(server_name, stream).await.map_err(ErrorFetch::ConnectTcp)
+}
+
+/// Sends a request for the path over the stream.
+#[cfg(feature = "net")]
+async fn write_request<S: AsyncWrite + Unpin>(stream: &mut S, host: &str, path: &str) -> Result<(), ErrorFetch> {
+	let request = format!("GET {path} HTTP/1.0\r\nHost: {host}\r\n\r\n");
+	stream.write_all(request.as_bytes()).await.map_err(ErrorFetch::RequestWrite)?;
+	stream.flush().await.map_err(ErrorFetch::RequestWrite)
+}
+
+/// Sends a request for the path over the stream, and reads the response into the buffer.
+#[cfg(feature = "net")]
+async fn exchange<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, host: &str, path: &str, buffer: &mut [u8]) -> Result<usize, ErrorFetch> {
+	write_request(stream, host, path).await?;
+	read_into(stream, buffer).await
+}
+
+/// Reads the stream to its end into the buffer and returns the number of bytes read.
+#[cfg(feature = "net")]
+async fn read_into<R: AsyncRead + Unpin>(stream: &mut R, buffer: &mut [u8]) -> Result<usize, ErrorFetch> {
 	let mut bytes_read = 0;
-	while let Ok(n) = stream.read(&mut buffer[bytes_read..]).await {
+	loop {
+		let n = match stream.read(&mut buffer[bytes_read..]).await {
+			Ok(n) => n,
+			Err(e) if is_clean_eof(&e, &buffer[..bytes_read]) => 0,
+			Err(e) => return Err(ErrorFetch::Read(e)),
+		};
 		if n == 0 { break; }
 		bytes_read += n;
+		if bytes_read == buffer.len() {
+			let mut probe = [0u8];
+			if stream.read(&mut probe).await.map_err(ErrorFetch::Read)? != 0 { return Err(ErrorFetch::BufferFull); }
+			break;
+		}
 	}
 	Ok(bytes_read)
 }
 
+/// Reads the stream to its end into the cleared vector and returns the number of bytes read.
+///
+/// Fails with [`ErrorFetch::BufferFull`] past [`MAX_RESPONSE_LEN`].
+#[cfg(feature = "net")]
+async fn read_into_vec<R: AsyncRead + Unpin>(stream: &mut R, buffer: &mut Vec<u8>) -> Result<usize, ErrorFetch> {
+	buffer.clear();
+	loop {
+		match stream.read_buf(buffer).await {
+			Ok(0) => break,
+			Ok(_) => check_response_len(buffer)?,
+			Err(e) if is_clean_eof(&e, buffer) => break,
+			Err(e) => return Err(ErrorFetch::Read(e)),
+		}
+	}
+	Ok(buffer.len())
+}
+
+/// Fails with [`ErrorFetch::BufferFull`] if the response grew past [`MAX_RESPONSE_LEN`].
+#[cfg(feature = "net")]
+fn check_response_len(response: &[u8]) -> Result<(), ErrorFetch> {
+	if response.len() > MAX_RESPONSE_LEN { return Err(ErrorFetch::BufferFull); }
+	Ok(())
+}
+
+/// Checks whether a read error is just the server closing the connection after the whole response.
+///
+/// The request is HTTP/1.0, so the server ends the body by closing the connection, and rustls
+/// reports a close without a TLS `close_notify` as [`UnexpectedEof`](std::io::ErrorKind::UnexpectedEof).
+#[cfg(feature = "net")]
+fn is_clean_eof(error: &std::io::Error, response: &[u8]) -> bool {
+	error.kind() == std::io::ErrorKind::UnexpectedEof && is_complete(response)
+}
+
+/// Checks whether an HTTP response is complete, as far as its framing tells.
+///
+/// A response without a `Content-Length` or chunked body is complete once its headers are.
+#[cfg(feature = "net")]
+fn is_complete(response: &[u8]) -> bool {
+	let Some(body) = body(response) else { return false };
+	let body = &response[body..];
+	let content_length = headers(response)
+		.find(|(name, _)| name.eq_ignore_ascii_case(b"content-length"))
+		.map(|(_, value)| atoi::atoi::<usize>(value));
+	match content_length {
+		Some(length) => length.is_some_and(|length| body.len() >= length),
+		None if is_chunked(response) => is_chunked_complete(body),
+		None => true,
+	}
+}
+
+/// Checks whether a chunked body is complete: it has its last (zero-size) chunk, with or without
+/// extensions, and then its trailer section up to the empty line.
+#[cfg(feature = "net")]
+fn is_chunked_complete(mut body: &[u8]) -> bool {
+	loop {
+		let Some(line_len) = memchr::memmem::find(body, b"\r\n") else { return false };
+		let line = &body[..line_len];
+		let size_len = line.iter().take_while(|c| c.is_ascii_hexdigit()).count();
+		let size = std::str::from_utf8(&line[..size_len]).ok()
+			.and_then(|size| usize::from_str_radix(size, 16).ok());
+		let Some(size) = size else { return false };
+		if !matches!(line.get(size_len), None | Some(b';' | b' ' | b'\t')) { return false; }
+		body = &body[line_len + 2..];
+		if size == 0 { break; }
+		let Some(rest) = body.get(size..).and_then(|rest| rest.strip_prefix(b"\r\n")) else { return false };
+		body = rest;
+	}
+	// The trailer section.
+	loop {
+		let Some(line_len) = memchr::memmem::find(body, b"\r\n") else { return false };
+		if line_len == 0 { return true; }
+		body = &body[line_len + 2..];
+	}
+}
+
+/// Checks whether the end of an HTTP response's body is told by its framing (`Content-Length` or
+/// chunked transfer encoding) rather than by the server closing the connection.
+#[cfg(feature = "net")]
+fn is_framed(response: &[u8]) -> bool {
+	body(response).is_some()
+		&& (headers(response).any(|(name, _)| name.eq_ignore_ascii_case(b"content-length")) || is_chunked(response))
+}
+
+/// Checks whether the server keeps the connection of an HTTP response open.
+#[cfg(feature = "net")]
+fn is_keep_alive(response: &[u8]) -> bool {
+	!response.starts_with(b"HTTP/1.0 ")
+		&& !headers(response).any(|(name, value)| name.eq_ignore_ascii_case(b"connection") && value.eq_ignore_ascii_case(b"close"))
+}
+
 /// Error when fetching PEMs.
+#[cfg(any(feature = "cache", feature = "reqwest"))]
 #[derive(Debug, thiserror::Error)]
 pub enum ErrorFetch {
+	#[error("invalid host")]
+	InvalidHost,
 	#[error("TCP connection error: {0}")]
-	Connect(tokio::io::Error),
+	Connect(std::io::Error),
 	#[error("TLS connection error: {0}")]
-	ConnectTcp(tokio::io::Error),
+	ConnectTcp(std::io::Error),
 	#[error("failed to write request: {0}")]
-	RequestWrite(tokio::io::Error),
+	RequestWrite(std::io::Error),
+	#[error("failed to read response: {0}")]
+	Read(std::io::Error),
+	#[error("the response doesn't fit in the buffer")]
+	BufferFull,
+	#[error("the buffer is shorter than fetch::MIN_BUFFER_LEN")]
+	BufferTooSmall,
+	#[error("timed out")]
+	Timeout,
+	#[error("the circuit breaker is open")]
+	CircuitOpen,
+	#[cfg(feature = "reqwest")]
+	#[error("reqwest error: {0}")]
+	Reqwest(#[from] reqwest::Error),
+	#[error("failed to process the response: {0}")]
+	Process(#[from] ErrorProcess),
+	/// An error of a [`KeyFetcher`] of your own, e.g. over a non-tokio HTTP client.
+	#[error("{0}")]
+	Other(Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// Fetches the PEM endpoint with the [`reqwest`] client and returns the keys expiration data and
+/// the body, which is for [`parse`](crate::parse()).
+///
+/// Unlike [`into`], this follows the client's configuration (proxies, compression, redirects).
+///
+/// # TLS
+/// The endpoint is HTTPS, but the `reqwest` feature builds reqwest without a TLS backend, so that
+/// it doesn't pick one for you. Enable one on your reqwest dependency, e.g. its `rustls-tls` or
+/// `native-tls` feature, or every fetch fails.
+#[cfg(feature = "reqwest")]
+pub async fn reqwest_body(client: &reqwest::Client) -> Result<(Age, Vec<u8>), ErrorFetch> {
+	reqwest_body_from(client, "https://www.googleapis.com/oauth2/v1/certs").await
+}
+
+/// Like [`reqwest_body`], but from the given URL.
+#[cfg(feature = "reqwest")]
+async fn reqwest_body_from(client: &reqwest::Client, url: &str) -> Result<(Age, Vec<u8>), ErrorFetch> {
+	let response = client.get(url).send().await?.error_for_status()?;
+	let header = |name| response.headers().get(name).map(reqwest::header::HeaderValue::as_bytes);
+	let max_age = header(reqwest::header::CACHE_CONTROL)
+		.and_then(|cache_control| find_prefixed_number(cache_control, b"max-age="))
+		.ok_or(ErrorProcess::MaxAge)?;
+	let age = header(reqwest::header::AGE).and_then(atoi::atoi).unwrap_or(0);
+	Ok((Age { age, max_age }, response.bytes().await?.into()))
+}
+
+/// A source of the keys, e.g. for [`cache::Keys`](crate::cache::Keys) to refresh from.
+///
+/// This decouples fetching from the network stack: besides [`Google`] (and, with the `reqwest`
+/// feature, [`reqwest::Client`]), any closure that returns a future of the result is a fetcher, e.g.
+/// one over the platform's HTTP client, which fails with [`ErrorFetch::Other`].
+///
+/// It only needs the `cache` feature, not `net`'s TCP/TLS stack.
+#[cfg(any(feature = "cache", feature = "reqwest"))]
+pub trait KeyFetcher {
+	/// Fetches the keys, and returns their expiration data and the (dechunked) PEM endpoint body,
+	/// which is for [`parse`](crate::parse()).
+	fn fetch(&self) -> impl std::future::Future<Output = Result<(Age, Vec<u8>), ErrorFetch>>;
+}
+
+#[cfg(any(feature = "cache", feature = "reqwest"))]
+impl<F: Fn() -> FUT, FUT: std::future::Future<Output = Result<(Age, Vec<u8>), ErrorFetch>>> KeyFetcher for F {
+	fn fetch(&self) -> impl std::future::Future<Output = Result<(Age, Vec<u8>), ErrorFetch>> { self() }
+}
+
+/// Fetches with [`reqwest_body`], so the client needs a [TLS backend](reqwest_body#tls).
+#[cfg(feature = "reqwest")]
+impl KeyFetcher for reqwest::Client {
+	fn fetch(&self) -> impl std::future::Future<Output = Result<(Age, Vec<u8>), ErrorFetch>> { reqwest_body(self) }
+}
+
+/// The [`KeyFetcher`] of Google's PEM endpoint, with `into_vec`, which requires the `net`
+/// feature.
+#[cfg(feature = "cache")]
+#[derive(Debug, Hash, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Google;
+
+#[cfg(feature = "net")]
+impl KeyFetcher for Google {
+	async fn fetch(&self) -> Result<(Age, Vec<u8>), ErrorFetch> {
+		let mut response = Vec::new();
+		into_vec(&mut response).await?;
+		split_body(response)
+	}
+}
+
+/// Splits a response into its keys expiration data and its (dechunked) body.
+#[cfg(feature = "net")]
+pub(crate) fn split_body(mut response: Vec<u8>) -> Result<(Age, Vec<u8>), ErrorFetch> {
+	let (age, body) = process_headers(&response)?;
+	#[cfg(feature = "tracing")]
+	tracing::debug!(age = age.age, max_age = age.max_age, "processed the response headers");
+	let mut body = response.split_off(body);
+	if is_chunked(&response) {
+		let len = dechunk(&mut body);
+		body.truncate(len);
+	}
+	Ok((age, body))
+}
+
+/// A circuit breaker for fetches.
+///
+/// After `threshold` consecutive failures within `window` seconds, the circuit opens and fetches
+/// fail fast with [`ErrorFetch::CircuitOpen`] for `cooldown` seconds. Then a single probe fetch is
+/// let through: if it succeeds the circuit closes, otherwise it opens for another cooldown.
+#[cfg(feature = "cache")]
+#[derive(Debug, Clone)]
+pub struct Breaker<INSTANT = SystemTime> {
+	threshold: u32,
+	window: u64,
+	cooldown: u64,
+	/// The consecutive failures in the current window.
+	failures: u32,
+	/// The end of the current failure window.
+	window_end: Option<INSTANT>,
+	/// The end of the cooldown, while the circuit isn't closed.
+	open_until: Option<INSTANT>,
+}
+
+/// The state of a [`Breaker`].
+#[cfg(feature = "cache")]
+#[derive(Debug, Hash, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerState {
+	/// Fetches go through.
+	Closed,
+	/// Fetches fail fast.
+	Open,
+	/// The next fetch probes whether to close the circuit.
+	HalfOpen,
+}
+
+#[cfg(feature = "cache")]
+impl<INSTANT: Instant> Breaker<INSTANT> {
+	/// Creates a new closed circuit breaker.
+	pub const fn new(threshold: u32, window: u64, cooldown: u64) -> Self {
+		Self { threshold, window, cooldown, failures: 0, window_end: None, open_until: None }
+	}
+
+	/// Gets the state of the circuit.
+	pub fn state(&self) -> BreakerState {
+		match &self.open_until {
+			None => BreakerState::Closed,
+			Some(open_until) if open_until.is_expired() => BreakerState::HalfOpen,
+			Some(_) => BreakerState::Open,
+		}
+	}
+
+	/// Runs the fetch through the circuit, or fails fast if it's open.
+	pub async fn call<T, E: From<ErrorFetch>>(&mut self, fetch: impl std::future::Future<Output = Result<T, E>>) -> Result<T, E> {
+		if self.state() == BreakerState::Open { return Err(ErrorFetch::CircuitOpen.into()) }
+		let result = fetch.await;
+		if result.is_ok() { self.succeed() } else { self.fail() }
+		result
+	}
+
+	/// Records a successful fetch.
+	fn succeed(&mut self) {
+		self.failures = 0;
+		self.window_end = None;
+		self.open_until = None;
+	}
+
+	/// Records a failed fetch.
+	fn fail(&mut self) {
+		if self.window_end.as_ref().is_none_or(Instant::is_expired) {
+			let mut window_end = INSTANT::now();
+			window_end.add_seconds(self.window);
+			self.window_end = Some(window_end);
+			self.failures = 0;
+		}
+		self.failures += 1;
+		if self.open_until.is_some() || self.failures >= self.threshold {
+			let mut open_until = INSTANT::now();
+			open_until.add_seconds(self.cooldown);
+			self.open_until = Some(open_until);
+		}
+	}
 }
 
 /// Instant / date-time types.
@@ -60,18 +681,51 @@ pub trait Instant {
 	fn is_before(&self, other: &Self) -> bool;
 	/// Adds seconds to the instant.
 	fn add_seconds(&mut self, seconds: u64);
+	/// Gets the time from `earlier` to the instant, or zero if `earlier` is later.
+	///
+	/// By default, it's found in whole seconds by [adding](Self::add_seconds) them to `earlier`;
+	/// instants that can tell it directly should override it.
+	fn saturating_duration_since(&self, earlier: &Self) -> std::time::Duration where Self: Clone {
+		// Whether `earlier` plus the seconds is past the instant.
+		let past = |seconds| {
+			let mut instant = earlier.clone();
+			instant.add_seconds(seconds);
+			self.is_before(&instant)
+		};
+		if past(0) { return std::time::Duration::ZERO; }
+		// Doubles up past the instant, then bisects, keeping `!past(low) && past(high)`.
+		let (mut low, mut high) = (0, 1);
+		while !past(high) {
+			if high == u64::MAX { return std::time::Duration::from_secs(high); }
+			low = high;
+			high = high.saturating_mul(2);
+		}
+		while high - low > 1 {
+			let mid = low + (high - low) / 2;
+			if past(mid) { high = mid } else { low = mid }
+		}
+		std::time::Duration::from_secs(low)
+	}
 
 	/// Checks if the given instant is expired, that is, if it already occurred.
 	fn is_expired(&self) -> bool where Self: Sized {
 		self.is_before(&Self::now())
 	}
+
+	/// Gets the time until the instant, or zero if it's expired.
+	fn time_left(&self) -> std::time::Duration where Self: Sized + Clone {
+		self.saturating_duration_since(&Self::now())
+	}
 }
 
 impl Instant for SystemTime {
 	fn now() -> Self { Self::now() }
 	fn is_before(&self, other: &Self) -> bool { self < other }
 	fn add_seconds(&mut self, seconds: u64) {
-		*self = *self + std::time::Duration::from_secs(seconds);
+		*self += std::time::Duration::from_secs(seconds);
+	}
+	fn saturating_duration_since(&self, earlier: &Self) -> std::time::Duration {
+		self.duration_since(*earlier).unwrap_or_default()
 	}
 }
 
@@ -79,7 +733,24 @@ impl Instant for std::time::Instant {
 	fn now() -> Self { Self::now() }
 	fn is_before(&self, other: &Self) -> bool { self < other }
 	fn add_seconds(&mut self, seconds: u64) {
-		*self = *self + std::time::Duration::from_secs(seconds);
+		*self += std::time::Duration::from_secs(seconds);
+	}
+	fn saturating_duration_since(&self, earlier: &Self) -> std::time::Duration {
+		Self::saturating_duration_since(self, *earlier)
+	}
+}
+
+/// Follows tokio's clock, so tests can [`pause`](tokio::time::pause) and
+/// [`advance`](tokio::time::advance) it.
+#[cfg(feature = "cache")]
+impl Instant for tokio::time::Instant {
+	fn now() -> Self { Self::now() }
+	fn is_before(&self, other: &Self) -> bool { self < other }
+	fn add_seconds(&mut self, seconds: u64) {
+		*self += std::time::Duration::from_secs(seconds);
+	}
+	fn saturating_duration_since(&self, earlier: &Self) -> std::time::Duration {
+		Self::saturating_duration_since(self, *earlier)
 	}
 }
 
@@ -93,37 +764,202 @@ pub struct Age<T = u64> {
 }
 
 impl Age<u64> {
+	/// Gets the seconds left to live, from the response time (0 if it's already stale).
+	pub fn ttl(&self) -> u64 { self.max_age.saturating_sub(self.age) }
+
 	/// Gets the expiration time, where `time` is the response time.
 	pub fn expiration<I: Instant>(self, mut time: I) -> I {
-		time.add_seconds(self.max_age - self.age);
+		time.add_seconds(self.ttl());
 		time
 	}
 
+	/// Checks whether the response is still fresh, where `time` is the response time.
+	///
+	/// It's fresh up to and including its [expiration](Self::expiration).
+	pub fn is_fresh<I: Instant>(&self, time: I) -> bool {
+		!self.expiration(time).is_expired()
+	}
+
 	/// Gets the expiration time. Must be called during the time of the response.
 	pub fn expiration_now<I: Instant>(self) -> I {
 		self.expiration(I::now())
 	}
+
+	/// Gets whichever of the ages has less time left to live.
+	///
+	/// A cache combining keys from several responses should expire by it.
+	pub fn min_ttl(a: &Self, b: &Self) -> Self {
+		if b.ttl() < a.ttl() { *b } else { *a }
+	}
 }
 
 /// Yields a tuple of the keys expiration data, and the [`body`] index.
+///
+/// Fails with [`ErrorProcess::Status`] for non-2xx responses, e.g. when rate limited (429),
+/// which is worth retrying later, unlike a malformed response.
+///
+/// Without an `Age` header, the age is derived from `Expires - Date` when both are present, as
+/// caches may omit it even well into the response's lifetime.
 pub fn process_headers(response: &[u8]) -> Result<(Age, usize), ErrorProcess> {
-	use memchr::memmem;
-
-	fn find_prefixed_number(data: &[u8], prefix: &[u8]) -> Option<u64> {
-		let prefix = memmem::find(data, prefix)? + prefix.len();
-		let prefix_len = data[prefix..].iter().copied().take_while(|&c| c.is_ascii_digit()).count();
-		let number = &data[prefix..prefix + prefix_len];
-		atoi::atoi(number)
+	/// Checks that no critical header is repeated with a different value.
+	fn check_unambiguous(response: &[u8]) -> Result<(), ErrorProcess> {
+		const CRITICAL: [&[u8]; 3] = [b"cache-control", b"content-length", b"age"];
+		let mut values: [Option<&[u8]>; CRITICAL.len()] = [None; CRITICAL.len()];
+		for (name, value) in headers(response) {
+			if let Some(i) = CRITICAL.iter().position(|critical| name.eq_ignore_ascii_case(critical)) {
+				match values[i] {
+					Some(previous) if previous != value => return Err(ErrorProcess::AmbiguousHeaders),
+					_ => values[i] = Some(value),
+				}
+			}
+		}
+		Ok(())
 	}
 
+	let status = status(response).ok_or(ErrorProcess::StatusLine)?;
+	if !(200..300).contains(&status) { return Err(ErrorProcess::Status(status)); }
+	check_unambiguous(response)?;
 	let skipped = memchr::memchr(b'\n', response).unwrap_or(0);
 	let response = &response[skipped..];
 	let max_age = find_prefixed_number(response, b"max-age=").ok_or(ErrorProcess::MaxAge)?;
-	let age = find_prefixed_number(response, b"Age: ").unwrap_or(0);
+	let age = find_prefixed_number(response, b"Age: ").unwrap_or_else(|| {
+		let CacheHeaders { expires, date, .. } = cache_headers(response);
+		expires.zip(date)
+			.and_then(|(expires, date)| expires.duration_since(date).ok())
+			.map_or(0, |remaining| max_age.saturating_sub(remaining.as_secs()))
+	});
 	let body = body(response).ok_or(ErrorProcess::Body)? + skipped;
 	Ok((Age { age, max_age }, body))
 }
 
+/// HTTP caching information beyond [`Age`].
+///
+/// See [`cache_headers`].
+#[derive(Debug, Hash, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheHeaders {
+	/// The [`Expires`](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Expires) header.
+	pub expires: Option<SystemTime>,
+	/// The [`Date`](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Date) header.
+	pub date: Option<SystemTime>,
+	/// Whether the [Cache-Control](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Cache-Control)
+	/// has [`must-revalidate`](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Cache-Control#must-revalidate).
+	pub must_revalidate: bool,
+}
+
+impl CacheHeaders {
+	/// Gets the expiration time: the `Expires` header if present, otherwise the [`Age`] from the
+	/// response time.
+	///
+	/// Unlike the [`Age`], `Expires` is absolute, so it doesn't depend on when the response was
+	/// received, but it does depend on the local clock agreeing with the server's.
+	pub fn expiration(&self, age: Age, response_time: SystemTime) -> SystemTime {
+		self.expires.unwrap_or_else(|| age.expiration(response_time))
+	}
+}
+
+/// Gets the [`CacheHeaders`] of an HTTP response.
+///
+/// Dates that aren't in the [IMF-fixdate](https://www.rfc-editor.org/rfc/rfc9110#section-5.6.7)
+/// format (e.g. `Sat, 27 Jan 2024 02:00:59 GMT`) are ignored.
+pub fn cache_headers(response: &[u8]) -> CacheHeaders {
+	let mut headers = CacheHeaders::default();
+	for (name, value) in self::headers(response) {
+		if name.eq_ignore_ascii_case(b"expires") {
+			headers.expires = http_date(value);
+		} else if name.eq_ignore_ascii_case(b"date") {
+			headers.date = http_date(value);
+		} else if name.eq_ignore_ascii_case(b"cache-control") {
+			headers.must_revalidate |= value.split(|&c| c == b',')
+				.any(|directive| directive.trim_ascii().eq_ignore_ascii_case(b"must-revalidate"));
+		}
+	}
+	headers
+}
+
+/// Parses an [IMF-fixdate](https://www.rfc-editor.org/rfc/rfc9110#section-5.6.7), e.g.
+/// `Sat, 27 Jan 2024 02:00:59 GMT`.
+fn http_date(date: &[u8]) -> Option<SystemTime> {
+	const MONTHS: [&[u8]; 12] = [b"Jan", b"Feb", b"Mar", b"Apr", b"May", b"Jun", b"Jul", b"Aug", b"Sep", b"Oct", b"Nov", b"Dec"];
+	let [_, _, _, b',', b' ', d @ .., b' ', b'G', b'M', b'T'] = date else { return None };
+	let number = |digits: &[u8]| digits.iter().all(u8::is_ascii_digit).then(|| atoi::atoi::<u64>(digits)).flatten();
+	let [d0, d1, b' ', m0, m1, m2, b' ', y0, y1, y2, y3, b' ', h0, h1, b':', n0, n1, b':', s0, s1] = *d else { return None };
+	let day = number(&[d0, d1])?;
+	let month = MONTHS.iter().position(|&month| month == [m0, m1, m2])? as u64 + 1;
+	let year = number(&[y0, y1, y2, y3])?;
+	let (hour, minute, second) = (number(&[h0, h1])?, number(&[n0, n1])?, number(&[s0, s1])?);
+	if year < 1970 || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 { return None; }
+
+	// Days since the UNIX epoch, per http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+	let year = if month <= 2 { year - 1 } else { year };
+	let era = year / 400;
+	let year_of_era = year - era * 400;
+	let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+	let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+	let days = era * 146097 + day_of_era - 719468;
+
+	let seconds = days * 86400 + hour * 3600 + minute * 60 + second;
+	SystemTime::UNIX_EPOCH.checked_add(std::time::Duration::from_secs(seconds))
+}
+
+/// Gets the status code of an HTTP response from its status line.
+pub fn status(response: &[u8]) -> Option<u16> {
+	let line = &response[..memchr::memchr(b'\n', response)?];
+	let mut parts = line.split(|&c| c == b' ');
+	if !parts.next()?.starts_with(b"HTTP/") { return None; }
+	let code = parts.next()?.trim_ascii();
+	if code.len() != 3 { return None; }
+	atoi::atoi(code)
+}
+
+/// Finds the number right after the first occurrence of the prefix.
+fn find_prefixed_number(data: &[u8], prefix: &[u8]) -> Option<u64> {
+	let prefix = memchr::memmem::find(data, prefix)? + prefix.len();
+	let prefix_len = data[prefix..].iter().copied().take_while(|&c| c.is_ascii_digit()).count();
+	let number = &data[prefix..prefix + prefix_len];
+	atoi::atoi(number)
+}
+
+/// Iterates over the (name, value) headers of an HTTP response.
+fn headers(response: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+	let headers = &response[..body(response).unwrap_or(response.len())];
+	headers.split(|&c| c == b'\n')
+		.skip(1) // status line
+		.filter_map(|line| {
+			let colon = memchr::memchr(b':', line)?;
+			Some((&line[..colon], line[colon + 1..].trim_ascii()))
+		})
+}
+
+/// Checks whether an HTTP response has a chunked body, in which case you want to [`dechunk`] it.
+///
+/// The request is HTTP/1.0 so that responses aren't chunked, but that's up to the server.
+pub fn is_chunked(response: &[u8]) -> bool {
+	headers(response).any(|(name, value)| {
+		name.eq_ignore_ascii_case(b"transfer-encoding")
+			&& value.split(|&c| c == b',').any(|coding| coding.trim_ascii().eq_ignore_ascii_case(b"chunked"))
+	})
+}
+
+/// Collapses a chunked body in place and returns its new length.
+///
+/// Stops at the last chunk, or at the first malformed one.
+pub fn dechunk(body: &mut [u8]) -> usize {
+	let (mut read, mut written) = (0, 0);
+	while let Some(line_len) = memchr::memmem::find(&body[read..], b"\r\n") {
+		let size_len = body[read..read + line_len].iter().take_while(|c| c.is_ascii_hexdigit()).count();
+		let size = std::str::from_utf8(&body[read..read + size_len]).ok()
+			.and_then(|size| usize::from_str_radix(size, 16).ok());
+		let Some(size) = size else { break };
+		read += line_len + 2;
+		if size == 0 || size > body.len() - read { break; }
+		body.copy_within(read..read + size, written);
+		written += size;
+		read += size + 2;
+		if read > body.len() { break; }
+	}
+	written
+}
+
 /// Gets the body index of an HTTP response.
 pub fn body(response: &[u8]) -> Option<usize> {
 	use memchr::memmem;
@@ -138,13 +974,20 @@ pub enum ErrorProcess {
 	MaxAge,
 	#[error("couldn't find response body")]
 	Body,
+	#[error("the response repeats a critical header with conflicting values")]
+	AmbiguousHeaders,
+	#[error("couldn't parse the response status line")]
+	StatusLine,
+	#[error("the response status is {0}")]
+	Status(u16),
 }
 
 #[cfg(test)]
 mod test {
 	use super::*;
 
-	static SAMPLE: &[u8] = b"HTTP/1.0 200 OK\r\nServer: scaffolding on HTTPServer2\r\nX-XSS-Protection: 0\r\nX-Frame-Options: SAMEORIGIN\r\nX-Content-Type-Options: nosniff\r\nDate: Fri, 26 Jan 2024 19:49:49 GMT\r\nExpires: Sat, 27 Jan 2024 02:00:59 GMT\r\nCache-Control: public, max-age=22270, must-revalidate, no-transform\r\nContent-Type: application/json; charset=UTF-8\r\nAge: 9\r\nAlt-Svc: h3=\":443\"; ma=2592000,h3-29=\":443\"; ma=2592000\r\nAccept-Ranges: none\r\nVary: Origin,X-Origin,Referer,Accept-Encoding\r\n\r\n{\n  \"48a63bc4767f8550a532dc630cf7eb49ff397e7c\": \"-----BEGIN CERTIFICATE-----\\nMIIDJjCCAg6gAwIBAgIITpARon8gBycwDQYJKoZIhvcNAQEFBQAwNjE0MDIGA1UE\\nAwwrZmVkZXJhdGVkLXNpZ25vbi5zeXN0ZW0uZ3NlcnZpY2VhY2NvdW50LmNvbTAe\\nFw0yNDAxMTUwNDM4MTNaFw0yNDAxMzExNjUzMTNaMDYxNDAyBgNVBAMMK2ZlZGVy\\nYXRlZC1zaWdub24uc3lzdGVtLmdzZXJ2aWNlYWNjb3VudC5jb20wggEiMA0GCSqG\\nSIb3DQEBAQUAA4IBDwAwggEKAoIBAQCrCvOXTp/AHo4ibrYjE0bs1c0gOaB0Gu9/\\nT2hvYaynpmYBeBTi2sc9Rit0FoCVTloelyFcJ/+ZUv5Tl3NGp5UVCxWqyPg8QgTo\\nTk4/DwTC6Y/Z/MtBKzCmQqYkkoVx2dx9DvfRAGidFQSEqQhuJh2JwmXnJOQ5F3T8\\nGZ90tX3yv6wTAQc3iXNMnXn7LD3Shv9Hq8AfjA/IJI3dd7n/NXpHgQ0vY2UqfYdP\\n2VtXseG1CieB5rzB+e2FSF1kffyQjhJLmcBoJU3EQDOW8m1Qh0KlKCNSBxtqH4PB\\njf2XgPzTSQvGRwXYIZc9KakXwY+zVpZKxi6ljyxNLL2oIUkU8XHxAgMBAAGjODA2\\nMAwGA1UdEwEB/wQCMAAwDgYDVR0PAQH/BAQDAgeAMBYGA1UdJQEB/wQMMAoGCCsG\\nAQUFBwMCMA0GCSqGSIb3DQEBBQUAA4IBAQBN1buL5aXabeBGUuQctOv5Op/yXrwx\\nsckGU0hPb1/9OBQzvJ1IXQ5XQBqyHLNI/alt1qAFp0Q/aY8G/Lf0FWlUZvRqYmJ1\\n34ZxZJBJRL2cl5cV3uke3meVcm4/MYIezJHA+VZ2ApVYWEYFU4757SwkKyXcP7vE\\nwInJwTcwNaEO7bpCD6UPGYUqX7HJ56woVDk/mq3Y7c2S7iloXODbivU+mHKNNowl\\nfp2cMnDCKAkNNFOJ9qGwv5VQ0ZLPn9P1c+0pjA9ym8Gq6AUUcDlf40PrmMi/X7iL\\nvEcijJS73YkPAMD+0X3DPsks2Y0HFZ4/zwELkbHQYgNeIwwEvT6AGvy6\\n-----END CERTIFICATE-----\\n\",\n  \"85e55107466b7e29836199c58c7581f5b923be44\": \"-----BEGIN CERTIFICATE-----\\nMIIDJzCCAg+gAwIBAgIJAIvQopve/48XMA0GCSqGSIb3DQEBBQUAMDYxNDAyBgNV\\nBAMMK2ZlZGVyYXRlZC1zaWdub24uc3lzdGVtLmdzZXJ2aWNlYWNjb3VudC5jb20w\\nHhcNMjQwMTIzMDQzODE0WhcNMjQwMjA4MTY1MzE0WjA2MTQwMgYDVQQDDCtmZWRl\\ncmF0ZWQtc2lnbm9uLnN5c3RlbS5nc2VydmljZWFjY291bnQuY29tMIIBIjANBgkq\\nhkiG9w0BAQEFAAOCAQ8AMIIBCgKCAQEA4tVDrq5RbeDtlJ2Xh2dikE840LWflr89\\nCm3cGI9mQGlskTigV0anoViOH92Z1sqWAp5e1aRkLlCm+KAWc69uvOW/X70jEhzD\\nJVREeB3h+RAnzxYrbUgDEgltiUaM8Zxtt8hiVh/GDAudRmSP9kDxXL5xnJETF1gn\\nwAHa0j7cM4STLKbtwKi73CEmTjTLqGAES8XVnXp8VWGb6IuQzdmBIJkfcFog4Inq\\n93F4Cj/SXsSjECG3j56VxgwnloPCHTXVn/xS1s3OjoBCOvOVSJfg2nSTWNi93JGR\\n9pWZevh7Sq8Clw8H2lvIAPV/HYdxvsucWg8sJuTa6ZZSxT1WmBkW6QIDAQABozgw\\nNjAMBgNVHRMBAf8EAjAAMA4GA1UdDwEB/wQEAwIHgDAWBgNVHSUBAf8EDDAKBggr\\nBgEFBQcDAjANBgkqhkiG9w0BAQUFAAOCAQEApInd0KdnkC03WXCAchOuIk9hCvoO\\nWKTlv0wapUx4I8F8qQBDkbDpRXhF4mxMwwemcIAtRWMf12wso9cukjnMw1xeo2ec\\nIaJFqHQGHsSXiU9XcIUhcS/X9tqXCVgY6FZUw9R/7k3fWw+se+R3sKKOKPUAt9sz\\n2AQ9F67emxiyVCgCD0nzx0sj0vy/Yr3GS9K4Y9UGMi2Vur8E2v/ZDko6VqcBFwIz\\ne1Vhwr5G8T6OsWf1xeEV+FpsUy2e14JhmsrNWYYMQgyxgBxH2LmNqyvudX7IVTsR\\n1Cep5Xa7BJbADYSEFiArwnlQ9p0QMNrzhPg7W8IoMMpDaSpQeQ1nYX2ecQ==\\n-----END CERTIFICATE-----\\n\"\n}\n";
+	/// A response of the PEM endpoint.
+	static SAMPLE: &[u8] = include_bytes!("../testdata/sample_response.http");
 
 	#[test]
 	fn test_body() {
@@ -158,6 +1001,535 @@ mod test {
 		}
 	}
 
+	#[cfg(feature = "net")]
+	#[tokio::test]
+	async fn test_read_into_vec_grows() {
+		let mut buffer = Vec::with_capacity(16);
+		let len = read_into_vec(&mut &SAMPLE[..], &mut buffer).await.unwrap();
+		assert_eq!(len, SAMPLE.len());
+		assert_eq!(buffer, SAMPLE);
+	}
+
+	#[cfg(feature = "net")]
+	#[tokio::test]
+	async fn test_read_into_buffer_full() {
+		let mut buffer = [0u8; 256];
+		assert!(matches!(read_into(&mut &SAMPLE[..], &mut buffer).await, Err(ErrorFetch::BufferFull)));
+		let mut buffer = vec![0u8; SAMPLE.len()];
+		assert_eq!(read_into(&mut &SAMPLE[..], &mut buffer).await.unwrap(), SAMPLE.len());
+	}
+
+	#[cfg(feature = "net")]
+	#[tokio::test]
+	async fn test_read_into_vec_max_response_len() {
+		let mut response = SAMPLE.to_vec();
+		response.resize(MAX_RESPONSE_LEN, b' ');
+		assert_eq!(read_into_vec(&mut &response[..], &mut Vec::new()).await.unwrap(), MAX_RESPONSE_LEN);
+		response.push(b' ');
+		assert!(matches!(read_into_vec(&mut &response[..], &mut Vec::new()).await, Err(ErrorFetch::BufferFull)));
+
+		// A keep-alive response that never ends.
+		let (mut stream, mut server) = tokio::io::duplex(1 << 10);
+		let server = tokio::spawn(async move {
+			server.write_all(b"HTTP/1.1 200 OK\r\n\r\n").await.unwrap();
+			// Until the client hangs up.
+			while server.write_all(&[b' '; 1 << 10]).await.is_ok() {}
+		});
+		assert!(matches!(exchange_keep_alive(&mut stream, HOST, PEM_PATH, &mut Vec::new()).await, Err(ErrorFetch::BufferFull)));
+		drop(stream);
+		server.await.unwrap();
+	}
+
+	/// A reader that fails with the error kind.
+	#[cfg(feature = "net")]
+	struct Failing(std::io::ErrorKind);
+
+	#[cfg(feature = "net")]
+	impl AsyncRead for Failing {
+		fn poll_read(self: std::pin::Pin<&mut Self>, _: &mut std::task::Context<'_>, _: &mut tokio::io::ReadBuf<'_>) -> std::task::Poll<std::io::Result<()>> {
+			std::task::Poll::Ready(Err(self.0.into()))
+		}
+	}
+
+	#[cfg(feature = "net")]
+	#[tokio::test]
+	async fn test_read_into_vec_large_response() {
+		let ids = ["k0", "k1", "k2", "k3"];
+		let mut response = SAMPLE[..body(SAMPLE).unwrap()].to_vec();
+		response.extend(crate::testing::certs_body(ids.map(|id| (id, crate::testing::RSA_A_CERT))));
+		assert!(response.len() > 5 << 10);
+
+		let mut buffer = Vec::new();
+		read_into_vec(&mut &response[..], &mut buffer).await.unwrap();
+		let mut keys = crate::keys::Keys::with_default_capacity();
+		assert!(keys.extend_response(&mut buffer).unwrap().0);
+		assert!(ids.iter().all(|id| keys.contains(id.as_bytes())));
+	}
+
+	#[cfg(feature = "net")]
+	#[tokio::test]
+	async fn test_read_error() {
+		let mut buffer = [0u8; 5 << 10];
+		let mut stream = (&SAMPLE[..100]).chain(Failing(std::io::ErrorKind::ConnectionReset));
+		assert!(matches!(read_into(&mut stream, &mut buffer).await, Err(ErrorFetch::Read(_))));
+		let mut stream = (&SAMPLE[..100]).chain(Failing(std::io::ErrorKind::ConnectionReset));
+		assert!(matches!(read_into_vec(&mut stream, &mut Vec::new()).await, Err(ErrorFetch::Read(_))));
+	}
+
+	#[cfg(feature = "net")]
+	#[tokio::test]
+	async fn test_read_abrupt_close() {
+		use std::io::ErrorKind::UnexpectedEof;
+		let mut buffer = [0u8; 5 << 10];
+		let mut stream = SAMPLE.chain(Failing(UnexpectedEof));
+		assert_eq!(read_into(&mut stream, &mut buffer).await.unwrap(), SAMPLE.len());
+		let mut stream = SAMPLE.chain(Failing(UnexpectedEof));
+		assert_eq!(read_into_vec(&mut stream, &mut Vec::new()).await.unwrap(), SAMPLE.len());
+
+		// Truncated in the headers.
+		let mut stream = (&SAMPLE[..100]).chain(Failing(UnexpectedEof));
+		assert!(matches!(read_into(&mut stream, &mut buffer).await, Err(ErrorFetch::Read(_))));
+		let mut stream = (&SAMPLE[..100]).chain(Failing(UnexpectedEof));
+		assert!(matches!(read_into_vec(&mut stream, &mut Vec::new()).await, Err(ErrorFetch::Read(_))));
+
+		// Chunked, with a trailer section.
+		let response = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n2\r\n{}\r\n0\r\nX: y\r\n\r\n";
+		let mut stream = (&response[..]).chain(Failing(UnexpectedEof));
+		assert_eq!(read_into_vec(&mut stream, &mut Vec::new()).await.unwrap(), response.len());
+	}
+
+	#[cfg(feature = "net")]
+	#[test]
+	fn test_is_complete() {
+		assert!(is_complete(SAMPLE));
+		assert!(!is_complete(&SAMPLE[..100]));
+		assert!(is_complete(b"HTTP/1.0 200 OK\r\nContent-Length: 2\r\n\r\n{}"));
+		assert!(!is_complete(b"HTTP/1.0 200 OK\r\nContent-Length: 2\r\n\r\n{"));
+		assert!(is_complete(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n2\r\n{}\r\n0\r\n\r\n"));
+		assert!(!is_complete(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n2\r\n{}\r\n"));
+		let chunked = |body: &[u8]| [&b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n"[..], body].concat();
+		assert!(is_complete(&chunked(b"2\r\n{}\r\n0;ext=1\r\n\r\n")));
+		assert!(is_complete(&chunked(b"2\r\n{}\r\n0\r\nX: y\r\n\r\n")));
+		assert!(is_complete(&chunked(b"2;a=b\r\n{}\r\n0;ext=1\r\nX: y\r\nZ: w\r\n\r\n")));
+		assert!(!is_complete(&chunked(b"2\r\n{}\r\n0\r\nX: y\r\n")));
+		// The end of a chunked body, but inside a chunk's data.
+		assert!(!is_complete(&chunked(b"9\r\nab\r\n0\r\n\r\n")));
+		assert!(!is_complete(&chunked(b"2x\r\n{}\r\n0\r\n\r\n")));
+	}
+
+	#[test]
+	fn test_process_headers_status() {
+		let response = b"HTTP/1.0 429 Too Many Requests\r\nContent-Type: text/html\r\n\r\n<html>slow down</html>";
+		assert_eq!(status(response), Some(429));
+		assert!(matches!(process_headers(response), Err(ErrorProcess::Status(429))));
+		assert!(matches!(process_headers(b"garbage\r\n\r\n{}"), Err(ErrorProcess::StatusLine)));
+		assert_eq!(status(SAMPLE), Some(200));
+	}
+
+	#[test]
+	fn test_cache_headers() {
+		let headers = cache_headers(SAMPLE);
+		let at = |seconds| SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(seconds);
+		assert_eq!(headers, CacheHeaders {
+			expires: Some(at(1706320859)),
+			date: Some(at(1706298589)),
+			must_revalidate: true,
+		});
+		// The sample's Expires is its Date plus its max-age.
+		let (age, _) = process_headers(SAMPLE).unwrap();
+		let date = headers.date.unwrap();
+		assert_eq!(headers.expiration(age, date), Age { age: 0, ..age }.expiration(date));
+		let headers = CacheHeaders { expires: None, ..headers };
+		assert_eq!(headers.expiration(age, date), age.expiration(date));
+		// Without the Age header, Expires - Date gives the same TTL as an age of 0.
+		let without_age = String::from_utf8_lossy(SAMPLE).replace("Age: 9\r\n", "");
+		assert_eq!(process_headers(without_age.as_bytes()).unwrap().0, Age { age: 0, ..age });
+		let earlier = without_age.replace("02:00:59", "01:59:19");
+		assert_eq!(process_headers(earlier.as_bytes()).unwrap().0, Age { age: 100, ..age });
+
+		let response = b"HTTP/1.0 200 OK\r\nExpires: 0\r\nCache-Control: public, max-age=100\r\n\r\n{}";
+		assert_eq!(cache_headers(response), CacheHeaders::default());
+		assert_eq!(http_date(b"Thu, 29 Feb 2024 23:59:60 GMT"), Some(at(1709251200)));
+	}
+
+	#[test]
+	fn test_process_headers_ambiguous() {
+		let response = b"HTTP/1.0 200 OK\r\nCache-Control: public, max-age=100\r\nContent-Length: 2\r\ncontent-length: 20\r\n\r\n{}";
+		assert!(matches!(process_headers(response), Err(ErrorProcess::AmbiguousHeaders)));
+		let response = b"HTTP/1.0 200 OK\r\nCache-Control: public, max-age=100\r\nContent-Length: 2\r\nContent-Length: 2\r\n\r\n{}";
+		assert!(process_headers(response).is_ok());
+	}
+
+	#[cfg(feature = "net")]
+	#[test]
+	fn test_split_body() {
+		let response = b"HTTP/1.1 200 OK\r\nCache-Control: public, max-age=100\r\nTransfer-Encoding: chunked\r\n\r\n2\r\n{}\r\n0\r\n\r\n";
+		assert_eq!(split_body(response.to_vec()).unwrap(), (Age { age: 0, max_age: 100 }, b"{}".to_vec()));
+		let (age, body) = split_body(SAMPLE.to_vec()).unwrap();
+		assert_eq!((age, &body[..]), (Age { age: 9, max_age: 22270 }, &SAMPLE[crate::fetch::body(SAMPLE).unwrap()..]));
+	}
+
+	#[test]
+	fn test_dechunk() {
+		let mut response = *b"HTTP/1.1 200 OK\r\nCache-Control: public, max-age=100\r\nTransfer-Encoding: chunked\r\n\r\n7\r\n{\"a\": \"\r\nb;ext=1\r\nhello world\r\n2\r\n\"}\r\n0\r\n\r\n";
+		assert!(is_chunked(&response));
+		let (_, body) = process_headers(&response).unwrap();
+		let len = dechunk(&mut response[body..]);
+		assert_eq!(&response[body..body + len], b"{\"a\": \"hello world\"}");
+		assert!(!is_chunked(SAMPLE));
+	}
+
+	#[test]
+	fn test_saturating_duration_since_default() {
+		/// An instant with only the required methods, in milliseconds.
+		#[derive(Clone)]
+		struct Millis(u64);
+		impl Instant for Millis {
+			fn now() -> Self { Self(0) }
+			fn is_before(&self, other: &Self) -> bool { self.0 < other.0 }
+			fn add_seconds(&mut self, seconds: u64) { self.0 += seconds * 1000; }
+		}
+		let since = |instant, earlier| Millis(instant).saturating_duration_since(&Millis(earlier));
+		assert_eq!(since(90_500, 0), std::time::Duration::from_secs(90));
+		assert_eq!(since(7_000_000, 1_000), std::time::Duration::from_secs(6999));
+		assert_eq!(since(1_000, 1_000), std::time::Duration::ZERO);
+		assert_eq!(since(0, 1_000), std::time::Duration::ZERO);
+	}
+
+	#[test]
+	fn test_min_ttl() {
+		// Older, but lives longer.
+		let a = Age { age: 100, max_age: 1000 };
+		let b = Age { age: 0, max_age: 500 };
+		assert_eq!(Age::min_ttl(&a, &b), b);
+		assert_eq!(Age::min_ttl(&b, &a), b);
+	}
+
+	#[cfg(feature = "net")]
+	#[test]
+	fn test_ttl_is_fresh() {
+		use crate::testing::MockInstant;
+		assert_eq!(Age { age: 9, max_age: 100 }.ttl(), 91);
+		// Stale on arrival, e.g. from a cache that overstayed.
+		assert_eq!(Age { age: 200, max_age: 100 }.ttl(), 0);
+
+		let age = Age { age: 100, max_age: 100 };
+		MockInstant::set(10);
+		assert_eq!(age.expiration(MockInstant(10)), MockInstant(10));
+		assert!(age.is_fresh(MockInstant(10)));
+		MockInstant::set(11);
+		assert!(!age.is_fresh(MockInstant(10)));
+		assert!(Age { age: 0, max_age: 1 }.is_fresh(MockInstant(10)));
+	}
+
+	#[cfg(feature = "net")]
+	#[tokio::test]
+	async fn test_buffer_too_small() {
+		// Fails before connecting, so this doesn't hit the network.
+		let mut buffer = [0u8; 10];
+		assert!(matches!(into(&mut buffer).await, Err(ErrorFetch::BufferTooSmall)));
+		assert!(matches!(into_jwk(&mut []).await, Err(ErrorFetch::BufferTooSmall)));
+		assert!(matches!(over(tokio::io::duplex(64).0, &mut buffer).await, Err(ErrorFetch::BufferTooSmall)));
+	}
+
+	#[cfg(feature = "net")]
+	#[tokio::test]
+	async fn test_timeout() {
+		// A server that never responds.
+		let (mut stream, _server) = tokio::io::duplex(64);
+		let mut buffer = [0u8; 64];
+		let fetch = read_into(&mut stream, &mut buffer);
+		assert!(matches!(with_timeout(Duration::from_millis(10), fetch).await, Err(ErrorFetch::Timeout)));
+	}
+
+	#[cfg(feature = "net")]
+	#[test]
+	fn test_with_age() {
+		let mut buffer = SAMPLE.to_vec();
+		let len = buffer.len();
+		buffer.extend_from_slice(b"HTTP/1.0 200 OK\r\nCache-Control: max-age=1"); // stale data past the response
+		let (age, body) = process_headers(SAMPLE).unwrap();
+		assert_eq!(with_age(&buffer, len).unwrap(), (len, age, body));
+		assert!(matches!(with_age(b"HTTP/1.0 503 Service Unavailable\r\n\r\n", 36), Err(ErrorFetch::Process(ErrorProcess::Status(503)))));
+	}
+
+	#[cfg(feature = "net")]
+	#[tokio::test]
+	async fn test_over() {
+		let (stream, mut server) = tokio::io::duplex(256);
+		let server = tokio::spawn(async move {
+			let mut request = Vec::new();
+			while !request.ends_with(b"\r\n\r\n") {
+				server.read_buf(&mut request).await.unwrap();
+			}
+			server.write_all(b"HTTP/1.0 200 OK\r\n\r\n{}").await.unwrap();
+			request
+		});
+		let mut buffer = [0u8; MIN_BUFFER_LEN];
+		let len = over(stream, &mut buffer).await.unwrap();
+		assert_eq!(&buffer[..len], b"HTTP/1.0 200 OK\r\n\r\n{}");
+		assert_eq!(server.await.unwrap(), format!("GET {PEM_PATH} HTTP/1.0\r\nHost: {HOST}\r\n\r\n").into_bytes());
+	}
+
+	#[cfg(feature = "net")]
+	#[tokio::test]
+	async fn test_breaker() {
+		use crate::testing::MockInstant;
+
+		let calls = std::cell::Cell::new(0);
+		let fetch = |ok: bool| {
+			let calls = &calls;
+			async move {
+				calls.set(calls.get() + 1);
+				if ok { Ok(()) } else { Err(ErrorFetch::BufferFull) }
+			}
+		};
+		MockInstant::set(0);
+		let mut breaker = Breaker::<MockInstant>::new(3, 60, 30);
+
+		// Failures spread beyond the window don't open the circuit.
+		breaker.call(fetch(false)).await.unwrap_err();
+		breaker.call(fetch(false)).await.unwrap_err();
+		MockInstant::set(61);
+		breaker.call(fetch(false)).await.unwrap_err();
+		assert_eq!(breaker.state(), BreakerState::Closed);
+
+		breaker.call(fetch(false)).await.unwrap_err();
+		breaker.call(fetch(false)).await.unwrap_err();
+		assert_eq!(breaker.state(), BreakerState::Open);
+		assert_eq!(calls.get(), 5);
+		for _ in 0..10 {
+			assert!(matches!(breaker.call(fetch(true)).await, Err(ErrorFetch::CircuitOpen)));
+		}
+		assert_eq!(calls.get(), 5);
+
+		// A failed probe reopens the circuit.
+		MockInstant::set(92);
+		assert_eq!(breaker.state(), BreakerState::HalfOpen);
+		breaker.call(fetch(false)).await.unwrap_err();
+		assert_eq!(breaker.state(), BreakerState::Open);
+
+		// A successful probe closes it.
+		MockInstant::set(123);
+		breaker.call(fetch(true)).await.unwrap();
+		assert_eq!(breaker.state(), BreakerState::Closed);
+		assert_eq!(calls.get(), 7);
+	}
+
+	/// The certificate and key of `localhost` and `127.0.0.1`.
+	#[cfg(feature = "net")]
+	fn localhost_identity() -> (rustls::pki_types::CertificateDer<'static>, rustls::pki_types::PrivateKeyDer<'static>) {
+		use rustls::pki_types::{CertificateDer, PrivatePkcs8KeyDer};
+		(
+			CertificateDer::from(&include_bytes!("../testdata/localhost.crt.der")[..]),
+			PrivatePkcs8KeyDer::from(&include_bytes!("../testdata/localhost.key.der")[..]).into(),
+		)
+	}
+
+	/// Serves the response over TLS on a local port to the given number of connections.
+	///
+	/// Returns the host and a handle to the requests.
+	#[cfg(feature = "net")]
+	async fn serve(connections: usize, config: rustls::ServerConfig, response: Vec<u8>) -> (String, tokio::task::JoinHandle<Vec<Vec<u8>>>) {
+		use tokio::net::TcpListener;
+
+		let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let host = format!("127.0.0.1:{}", listener.local_addr().unwrap().port());
+		let server = tokio::spawn(async move {
+			let mut requests = Vec::with_capacity(connections);
+			for _ in 0..connections {
+				let (stream, _) = listener.accept().await.unwrap();
+				let mut stream = acceptor.accept(stream).await.unwrap();
+				let mut request = Vec::new();
+				while !request.ends_with(b"\r\n\r\n") {
+					stream.read_buf(&mut request).await.unwrap();
+				}
+				stream.write_all(&response).await.unwrap();
+				stream.shutdown().await.unwrap();
+				requests.push(request);
+			}
+			requests
+		});
+		(host, server)
+	}
+
+	/// A connector that trusts [`localhost_identity`].
+	#[cfg(feature = "net")]
+	fn localhost_connector() -> TlsConnector {
+		let mut roots = rustls::RootCertStore::empty();
+		roots.add(localhost_identity().0).unwrap();
+		connector_with_roots(roots, Arc::new(rustls::client::ClientSessionMemoryCache::new(SESSION_CACHE_SIZE)))
+	}
+
+	#[cfg(feature = "net")]
+	#[tokio::test]
+	async fn test_persistent() {
+		use tokio::net::TcpListener;
+
+		let (cert, key) = localhost_identity();
+		let config = rustls::ServerConfig::builder().with_no_client_auth().with_single_cert(vec![cert], key).unwrap();
+		let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let host = format!("127.0.0.1:{}", listener.local_addr().unwrap().port());
+		const KEEP_ALIVE: &[u8] = b"HTTP/1.1 200 OK\r\nCache-Control: public, max-age=100\r\nContent-Length: 2\r\n\r\n{}";
+		const CHUNKED: &[u8] = b"HTTP/1.1 200 OK\r\nCache-Control: public, max-age=100\r\nTransfer-Encoding: chunked\r\n\r\n2\r\n{}\r\n0\r\n\r\n";
+		const CLOSE: &[u8] = b"HTTP/1.1 200 OK\r\nCache-Control: public, max-age=100\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}";
+		// The responses of each connection; the server closes a connection after its last one.
+		let connections: [&[&[u8]]; 3] = [&[KEEP_ALIVE, CHUNKED, CLOSE], &[KEEP_ALIVE], &[KEEP_ALIVE]];
+		let server = tokio::spawn(async move {
+			for responses in connections {
+				let (stream, _) = listener.accept().await.unwrap();
+				let mut stream = acceptor.accept(stream).await.unwrap();
+				for response in responses {
+					let mut request = Vec::new();
+					while !request.ends_with(b"\r\n\r\n") {
+						stream.read_buf(&mut request).await.unwrap();
+					}
+					assert!(request.starts_with(b"GET /certs HTTP/1.1\r\n"));
+					stream.write_all(response).await.unwrap();
+				}
+				stream.shutdown().await.unwrap();
+			}
+		});
+
+		let fetcher = Persistent::with(localhost_connector(), &host, "/certs");
+		let mut buffer = Vec::new();
+		for response in [KEEP_ALIVE, CHUNKED, CLOSE] {
+			fetcher.into_vec(&mut buffer).await.unwrap();
+			assert_eq!(buffer, response);
+		}
+		// Reconnects after the server asked to close.
+		assert_eq!(fetcher.fetch().await.unwrap(), (Age { age: 0, max_age: 100 }, b"{}".to_vec()));
+		// Reconnects after the server closed the connection on its own.
+		fetcher.into_vec(&mut buffer).await.unwrap();
+		assert_eq!(buffer, KEEP_ALIVE);
+		server.await.unwrap();
+	}
+
+	#[cfg(feature = "net")]
+	#[tokio::test]
+	async fn test_into_from() {
+		use crate::testing;
+
+		let (cert, key) = localhost_identity();
+		let config = rustls::ServerConfig::builder().with_no_client_auth().with_single_cert(vec![cert], key).unwrap();
+		let mut response = b"HTTP/1.0 200 OK\r\nCache-Control: public, max-age=100\r\n\r\n".to_vec();
+		response.extend(testing::certs_body([("a", testing::RSA_A_CERT)]));
+		let (host, server) = serve(1, config, response).await;
+
+		let connector = localhost_connector();
+		let mut buffer = [0u8; 4096];
+		let len = into_from_with(&connector, &host, "/certs", &mut buffer).await.unwrap();
+		let requests = server.await.unwrap();
+		assert_eq!(requests[0], format!("GET /certs HTTP/1.0\r\nHost: {host}\r\n\r\n").into_bytes());
+
+		let response = &mut buffer[..len];
+		let (age, body) = process_headers(response).unwrap();
+		assert_eq!(age.max_age, 100);
+		let mut keys = crate::keys::Keys::<1>::new();
+		assert!(keys.try_extend(crate::parse(&mut response[body..])).unwrap());
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &testing::claims(3600));
+		keys.validate::<serde_json::Value>(&token).unwrap();
+
+		assert!(matches!(into_from_with(&connector, "127.0.0.1:port", "/certs", &mut buffer).await, Err(ErrorFetch::InvalidHost)));
+	}
+
+	#[cfg(feature = "net")]
+	#[tokio::test]
+	async fn test_with_config_pinning() {
+		let (cert, key) = localhost_identity();
+		let server_config = rustls::ServerConfig::builder().with_no_client_auth().with_single_cert(vec![cert.clone()], key).unwrap();
+		let client_config = |root| {
+			let mut roots = rustls::RootCertStore::empty();
+			roots.add(root).unwrap();
+			Arc::new(rustls::ClientConfig::builder().with_root_certificates(roots).with_no_client_auth())
+		};
+		let mut buffer = [0u8; MIN_BUFFER_LEN];
+
+		let (host, server) = serve(1, server_config.clone(), b"HTTP/1.0 200 OK\r\n\r\n{}".to_vec()).await;
+		let pinned = with_config(client_config(cert));
+		assert!(pinned.into_from(&host, "/certs", &mut buffer).await.is_ok());
+		server.await.unwrap();
+
+		let other = rustls::pki_types::CertificateDer::from(pem_der(crate::testing::RSA_A_CERT));
+		let (host, server) = serve(1, server_config, Vec::new()).await;
+		let pinned_other = with_config(client_config(other));
+		assert!(matches!(pinned_other.into_from(&host, "/certs", &mut buffer).await, Err(ErrorFetch::ConnectTcp(_))));
+		server.abort();
+	}
+
+	/// Decodes the DER of a PEM certificate.
+	#[cfg(feature = "net")]
+	fn pem_der(pem: &str) -> Vec<u8> {
+		use base64::Engine;
+		let base64 = pem.lines().filter(|line| !line.starts_with("-----")).collect::<String>();
+		base64::engine::general_purpose::STANDARD.decode(base64).unwrap()
+	}
+
+	#[cfg(feature = "net")]
+	#[tokio::test]
+	async fn test_session_resumption() {
+		use std::sync::atomic::{AtomicUsize, Ordering};
+
+		/// Server session storage that counts resumed sessions.
+		#[derive(Debug)]
+		struct Counting {
+			sessions: Arc<rustls::server::ServerSessionMemoryCache>,
+			resumed: Arc<AtomicUsize>,
+		}
+		impl rustls::server::StoresServerSessions for Counting {
+			fn put(&self, key: Vec<u8>, value: Vec<u8>) -> bool { self.sessions.put(key, value) }
+			fn get(&self, key: &[u8]) -> Option<Vec<u8>> { self.count(self.sessions.get(key)) }
+			fn take(&self, key: &[u8]) -> Option<Vec<u8>> { self.count(self.sessions.take(key)) }
+			fn can_cache(&self) -> bool { self.sessions.can_cache() }
+		}
+		impl Counting {
+			fn count(&self, session: Option<Vec<u8>>) -> Option<Vec<u8>> {
+				if session.is_some() { self.resumed.fetch_add(1, Ordering::SeqCst); }
+				session
+			}
+		}
+
+		let resumed = Arc::new(AtomicUsize::new(0));
+		let (cert, key) = localhost_identity();
+		let mut config = rustls::ServerConfig::builder().with_no_client_auth().with_single_cert(vec![cert], key).unwrap();
+		config.session_storage = Arc::new(Counting {
+			sessions: rustls::server::ServerSessionMemoryCache::new(8),
+			resumed: resumed.clone(),
+		});
+		let (host, server) = serve(2, config, b"HTTP/1.0 200 OK\r\n\r\n".to_vec()).await;
+
+		let connector = localhost_connector();
+		let mut buffer = [0u8; MIN_BUFFER_LEN];
+		into_from_with(&connector, &host, "/certs", &mut buffer).await.unwrap();
+		assert_eq!(resumed.load(Ordering::SeqCst), 0);
+		into_from_with(&connector, &host, "/certs", &mut buffer).await.unwrap();
+		assert_eq!(resumed.load(Ordering::SeqCst), 1);
+		server.await.unwrap();
+	}
+
+	#[cfg(feature = "reqwest")]
+	#[tokio::test]
+	async fn test_reqwest_body() {
+		use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::TcpListener};
+
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let url = format!("http://{}/certs", listener.local_addr().unwrap());
+		let server = tokio::spawn(async move {
+			let (mut stream, _) = listener.accept().await.unwrap();
+			let mut request = Vec::new();
+			while !request.ends_with(b"\r\n\r\n") {
+				stream.read_buf(&mut request).await.unwrap();
+			}
+			stream.write_all(b"HTTP/1.1 200 OK\r\nCache-Control: public, max-age=100\r\nAge: 7\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}").await.unwrap();
+		});
+		let (age, body) = reqwest_body_from(&reqwest::Client::new(), &url).await.unwrap();
+		server.await.unwrap();
+		assert_eq!(age, Age { age: 7, max_age: 100 });
+		assert_eq!(body, b"{}");
+	}
+
 	#[test]
 	fn test_process_headers() {
 		let (age, body) = process_headers(SAMPLE).unwrap();
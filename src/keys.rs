@@ -1,49 +1,151 @@
 //! Google PEM [`Keys`].
 
-use std::{mem::MaybeUninit, hash::{DefaultHasher, Hasher}, fmt::Debug};
+use std::{mem::MaybeUninit, hash::{DefaultHasher, Hasher}, fmt::Debug, sync::Arc};
 use std::hash::Hash;
 
-use jsonwebtoken::DecodingKey;
+use jsonwebtoken::{Algorithm, DecodingKey, errors::ErrorKind};
 use once_cell::sync::Lazy;
 
-const KEYS_CAPACITY: usize = 2 + /* slack */ 2;
+/// The default [`Keys`] capacity.
+pub const KEYS_CAPACITY: usize = 2 + /* slack */ 2;
 
 /// Google PEM keys.
 ///
+/// Holds up to `N` keys; Google usually publishes two at a time, three around rotations.
+///
 /// See [`Keys::extend_fetch`].
-pub struct Keys {
-	id: [MaybeUninit<u64>; KEYS_CAPACITY],
-	key: [MaybeUninit<DecodingKey>; KEYS_CAPACITY],
-	len: u8,
+pub struct Keys<const N: usize = KEYS_CAPACITY> {
+	id: [MaybeUninit<u64>; N],
+	key: [MaybeUninit<Key>; N],
+	len: usize,
+}
+
+/// A stored key.
+struct Key {
+	key: DecodingKey,
+	kind: KeyKind,
+	/// The key ID, as it was given.
+	id: Box<[u8]>,
+	/// The public components, if known, for [`Keys::to_jwks_json`].
+	components: Option<crate::parse::Components>,
+}
+
+impl Key {
+	/// Decodes a key from an RSA or EC PEM.
+	fn from_pem(id: &[u8], key: &[u8]) -> Result<Self, jsonwebtoken::errors::Error> {
+		let components = crate::parse::pem_components(key);
+		let (key, kind) = decode_pem(key)?;
+		Ok(Self { key, kind, id: id.into(), components })
+	}
+}
+
+/// The kind of a key, which determines the algorithms it validates.
+#[derive(Debug, Hash, Clone, Copy, PartialEq, Eq)]
+pub enum KeyKind {
+	/// An RSA key.
+	Rsa,
+	/// An elliptic curve key.
+	Ec,
+}
+
+impl KeyKind {
+	/// Gets the algorithms of keys of this kind.
+	pub const fn algorithms(self) -> &'static [Algorithm] {
+		match self {
+			Self::Rsa => &[Algorithm::RS256, Algorithm::RS384, Algorithm::RS512, Algorithm::PS256, Algorithm::PS384, Algorithm::PS512],
+			Self::Ec => &[Algorithm::ES256, Algorithm::ES384],
+		}
+	}
+}
+
+impl<const N: usize> Default for Keys<N> { fn default() -> Self { Self::new() } }
+
+impl<'a, const N: usize> IntoIterator for &'a Keys<N> {
+	type Item = (u64, &'a DecodingKey);
+	type IntoIter = Iter<'a>;
+	fn into_iter(self) -> Self::IntoIter { self.iter() }
+}
+
+/// An iterator over the (key ID hash, key) of [`Keys`]; see [`Keys::iter`].
+pub struct Iter<'a> {
+	/// The initialized keys.
+	keys: std::iter::Zip<std::slice::Iter<'a, MaybeUninit<u64>>, std::slice::Iter<'a, MaybeUninit<Key>>>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+	type Item = (u64, &'a DecodingKey);
+
+	fn next(&mut self) -> Option<Self::Item> {
+		self.keys.next().map(|(id, key)| unsafe { (id.assume_init(), &key.assume_init_ref().key) })
+	}
+
+	fn size_hint(&self) -> (usize, Option<usize>) { self.keys.size_hint() }
 }
 
-impl Default for Keys { fn default() -> Self { Self::new() } }
+impl ExactSizeIterator for Iter<'_> {}
+
+impl<const N: usize> Clone for Keys<N> {
+	fn clone(&self) -> Self {
+		let mut keys = Self::new();
+		for key in self.stored() {
+			keys.push_decoded(&key.id, key.key.clone(), key.kind, key.components.clone());
+		}
+		keys
+	}
+}
+
+/// Shows the IDs and kinds of the keys, but not the keys themselves.
+impl<const N: usize> Debug for Keys<N> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("Keys")
+			.field("len", &self.len)
+			.field("keys", &self.stored().map(|key| (String::from_utf8_lossy(&key.id), key.kind)).collect::<Vec<_>>())
+			.finish()
+	}
+}
+
+impl<const N: usize> Drop for Keys<N> {
+	fn drop(&mut self) {
+		for key in &mut self.key[..self.len] { unsafe { key.assume_init_drop(); } }
+	}
+}
 
 impl Keys {
+	/// New empty set of keys of the [default capacity](KEYS_CAPACITY), for where
+	/// [`new`](Self::new) can't infer the capacity.
+	pub const fn with_default_capacity() -> Self { Self::new() }
+}
+
+impl<const N: usize> Keys<N> {
 	/// New empty set of keys.
 	pub const fn new() -> Self {
 		Self {
-			id: unsafe { MaybeUninit::<[MaybeUninit<u64>; KEYS_CAPACITY]>::uninit().assume_init() },
-			key: unsafe { MaybeUninit::<[MaybeUninit<DecodingKey>; KEYS_CAPACITY]>::uninit().assume_init() },
+			id: unsafe { MaybeUninit::<[MaybeUninit<u64>; N]>::uninit().assume_init() },
+			key: unsafe { MaybeUninit::<[MaybeUninit<Key>; N]>::uninit().assume_init() },
 			len: 0,
 		}
 	}
 
 	/// Gets the count of keys.
-	pub const fn len(&self) -> usize { self.len as usize }
+	pub const fn len(&self) -> usize { self.len }
 	/// Gets whether there are no keys.
 	pub const fn is_empty(&self) -> bool { self.len == 0 }
+	/// Gets the count of keys there's room for, i.e. `N`.
+	pub const fn capacity(&self) -> usize { N }
 	/// Clears all the keys.
-	pub fn clear(&mut self) { self.len = 0; }
+	pub fn clear(&mut self) {
+		let len = std::mem::take(&mut self.len);
+		for key in &mut self.key[..len] { unsafe { key.assume_init_drop(); } }
+	}
 
 	/// Pushes a key.
 	///
 	/// # Safety
-	/// Ensure [`self.len()`](Self::len()) < `KEYS_CAPACITY`.
+	/// Ensure [`self.len()`](Self::len()) < `N`.
 	unsafe fn push_unchecked(&mut self, id: &[u8], key: &[u8]) -> Result<(), jsonwebtoken::errors::Error> {
-		debug_assert!(self.len() < KEYS_CAPACITY);
+		debug_assert!(self.len() < N);
 		let id_hash = hash(id);
-		let key = DecodingKey::from_rsa_pem(key)?;
+		let key = Key::from_pem(id, key)?;
 		let i = self.len();
 		*self.id.get_unchecked_mut(i) = MaybeUninit::new(id_hash);
 		*self.key.get_unchecked_mut(i) = MaybeUninit::new(key);
@@ -51,70 +153,994 @@ impl Keys {
 		Ok(())
 	}
 
-	/// Pushes a key.
+	/// Pushes an already decoded RSA key.
+	///
+	/// Returns whether there was space for it.
+	pub fn push_key(&mut self, id: &[u8], key: DecodingKey) -> bool {
+		self.push_key_of_kind(id, key, KeyKind::Rsa)
+	}
+
+	/// Pushes an already decoded key of the given kind.
+	///
+	/// Returns whether there was space for it.
+	///
+	/// Keys pushed this way are left out of [`to_jwks_json`](Self::to_jwks_json), as their
+	/// components are unknown.
+	pub fn push_key_of_kind(&mut self, id: &[u8], key: DecodingKey, kind: KeyKind) -> bool {
+		self.push_decoded(id, key, kind, None)
+	}
+
+	/// Pushes an already decoded key, with its components if known.
+	///
+	/// Returns whether there was space for it.
+	fn push_decoded(&mut self, id: &[u8], key: DecodingKey, kind: KeyKind, components: Option<crate::parse::Components>) -> bool {
+		if self.len() >= N { return false; }
+		let i = self.len();
+		self.id[i] = MaybeUninit::new(hash(id));
+		self.key[i] = MaybeUninit::new(Key { key, kind, id: id.into(), components });
+		self.len += 1;
+		true
+	}
+
+	/// Pushes a key, an RSA or EC PEM.
 	///
 	/// Returns whether there was space for it.
 	/// Fails if failed to parse the key.
 	pub fn push(&mut self, id: &[u8], key: &[u8]) -> Result<bool, jsonwebtoken::errors::Error> {
-		if self.len() >= KEYS_CAPACITY { return Ok(false); }
+		if self.len() >= N { return Ok(false); }
 		unsafe { self.push_unchecked(id, key)?; }
 		Ok(true)
 	}
 
 	/// Extends from an iterator of keys.
 	///
-	/// Returns whether there was room for all keys.
-	pub fn extend_try<'i>(&mut self, iter: impl IntoIterator<Item = (&'i [u8], &'i [u8])>) -> Result<bool, jsonwebtoken::errors::Error> {
+	/// Returns whether there was room for all keys, i.e. whether the set fits the
+	/// [`capacity`](Self::capacity); see [`try_extend_dropped`](Self::try_extend_dropped) for which
+	/// keys didn't fit.
+	///
+	/// This stops at the first key there's no room for, so at most `N` keys are ever
+	/// parsed, however many the iterator (e.g. an untrusted response) would yield.
+	/// That makes `N` the maximum count of keys to parse: e.g. `Keys::<8>` parses at most 8, and
+	/// [`try_extend_dropped`](Self::try_extend_dropped) tells which keys were cut off.
+	///
+	/// ```
+	/// # fn main() -> Result<(), jsonwebtoken::errors::Error> {
+	/// let cert = include_str!("../testdata/rsa_a.crt").replace('\n', "\\n");
+	/// let mut body = format!(r#"{{"a": "{cert}", "b": "{cert}"}}"#).into_bytes();
+	///
+	/// let mut keys = google_pem::keys::Keys::with_default_capacity();
+	/// assert!(keys.try_extend(google_pem::parse(&mut body))?);
+	/// assert!(keys.contains(b"a") && keys.contains(b"b"));
+	/// # Ok(())
+	/// # }
+	/// ```
+	pub fn try_extend<'i>(&mut self, iter: impl IntoIterator<Item = (&'i [u8], &'i [u8])>) -> Result<bool, jsonwebtoken::errors::Error> {
+		for (id, key) in iter {
+			if !self.push(id, key)? {
+				#[cfg(feature = "tracing")]
+				tracing::warn!(capacity = N, "there's no room for all the keys");
+				return Ok(false)
+			}
+		}
+		Ok(true)
+	}
+
+	/// Extends from an iterator of keys, like [`try_extend`](Self::try_extend), but returns the IDs
+	/// of the keys there was no room for rather than whether there was room for all of them.
+	///
+	/// A non-empty result means the capacity `N` is too small for the set.
+	pub fn try_extend_dropped<'i>(&mut self, iter: impl IntoIterator<Item = (&'i [u8], &'i [u8])>) -> Result<DroppedIds, jsonwebtoken::errors::Error> {
+		let mut iter = iter.into_iter();
+		for (id, key) in iter.by_ref() {
+			if !self.push(id, key)? {
+				return Ok(std::iter::once(id).chain(iter.map(|(id, _)| id)).map(Box::from).collect());
+			}
+		}
+		Ok(Vec::new())
+	}
+
+	/// Merges keys into this set: keys whose ID is already present replace the existing key, and
+	/// the others are added.
+	///
+	/// Returns whether there was room for all keys. Like [`try_extend`](Self::try_extend), this
+	/// stops at the first key there's no room for.
+	pub fn merge<'i>(&mut self, iter: impl IntoIterator<Item = (&'i [u8], &'i [u8])>) -> Result<bool, jsonwebtoken::errors::Error> {
 		for (id, key) in iter {
-			if !self.push(id, key)? { return Ok(false) }
+			match self.position(id) {
+				Some(i) => *unsafe { self.key[i].assume_init_mut() } = Key::from_pem(id, key)?,
+				None => if !self.push(id, key)? { return Ok(false) },
+			}
 		}
 		Ok(true)
 	}
 
-	/// [fetches](crate::fetch::into) keys and [extends](Self::extend_try) this set with them, using the given buffer.
+	/// Creates a set of keys from the certs endpoint body in the named environment variable.
+	///
+	/// This is for deployments that provision the keys rather than fetch them.
+	pub fn from_env(var: &str) -> Result<Self, FromEnvError> {
+		let mut body = std::env::var(var)?.into_bytes();
+		Self::from_certs_json(&mut body).map_err(|e| match e {
+			FromCertsJsonError::Jwt(e) => FromEnvError::Jwt(e),
+			FromCertsJsonError::Capacity => FromEnvError::Capacity,
+		})
+	}
+
+	/// Creates a set of keys from a certs endpoint body that's already at hand, without fetching.
+	///
+	/// The body is parsed in place.
+	pub fn from_certs_json(data: &mut [u8]) -> Result<Self, FromCertsJsonError> {
+		let mut keys = Self::new();
+		if !keys.try_extend(crate::parse(data))? { return Err(FromCertsJsonError::Capacity); }
+		Ok(keys)
+	}
+
+	/// Extends from an iterator of keys.
+	#[deprecated = "renamed to `try_extend`"]
+	pub fn extend_try<'i>(&mut self, iter: impl IntoIterator<Item = (&'i [u8], &'i [u8])>) -> Result<bool, jsonwebtoken::errors::Error> {
+		self.try_extend(iter)
+	}
+
+	/// [fetches](crate::fetch::into) keys and [extends](Self::try_extend) this set with them, using the given buffer.
+	#[cfg(feature = "net")]
 	pub async fn extend_fetch_into(&mut self, buffer: &mut [u8]) -> Result<(bool, crate::fetch::Age), FetchExtendError> {
 		let len = crate::fetch::into(buffer).await?;
-		let (age, body) = crate::fetch::process_headers(&buffer)?;
-		let all_fit = self.extend_try(crate::parse(&mut buffer[body..len]))?;
+		self.extend_response(&mut buffer[..len])
+	}
+
+	/// [Extends](Self::try_extend) this set with the keys of a fetched HTTP response.
+	#[cfg(feature = "net")]
+	pub(crate) fn extend_response(&mut self, response: &mut [u8]) -> Result<(bool, crate::fetch::Age), FetchExtendError> {
+		let (age, body) = crate::fetch::process_headers(response)?;
+		let body = dechunked(response, body);
+		let all_fit = self.try_extend(crate::parse(body))?;
 		Ok((all_fit, age))
 	}
 
-	/// [fetches](crate::fetch::into) keys and [extends](Self::extend_try) this set with them.
+	/// [fetches](crate::fetch::into_vec) keys and [extends](Self::try_extend) this set with them.
+	///
+	/// The response is read into a vector that grows to fit it; see
+	/// [`extend_fetch_into`](Self::extend_fetch_into) for a buffer of your own.
+	#[cfg(feature = "net")]
 	pub async fn extend_fetch(&mut self) -> Result<(bool, crate::fetch::Age), FetchExtendError> {
-		let mut buffer = [0u8; 5 << 10];
-		self.extend_fetch_into(&mut buffer).await
+		let mut buffer = Vec::new();
+		crate::fetch::into_vec(&mut buffer).await?;
+		self.extend_response(&mut buffer)
+	}
+
+	/// [fetches](crate::fetch::into_vec) keys and extends this set with them, returning the IDs of
+	/// the keys there was no room for (see [`try_extend_dropped`](Self::try_extend_dropped)).
+	#[cfg(feature = "net")]
+	pub async fn extend_fetch_dropped(&mut self) -> Result<(DroppedIds, crate::fetch::Age), FetchExtendError> {
+		let mut buffer = Vec::new();
+		crate::fetch::into_vec(&mut buffer).await?;
+		self.extend_response_dropped(&mut buffer)
+	}
+
+	/// [Extends](Self::try_extend_dropped) this set with the keys of a fetched HTTP response.
+	#[cfg(feature = "net")]
+	fn extend_response_dropped(&mut self, response: &mut [u8]) -> Result<(DroppedIds, crate::fetch::Age), FetchExtendError> {
+		let (age, body) = crate::fetch::process_headers(response)?;
+		let body = dechunked(response, body);
+		let dropped = self.try_extend_dropped(crate::parse(body))?;
+		Ok((dropped, age))
+	}
+
+	/// [fetches](crate::fetch::into_jwk) JWK keys and adds them to this set, using the given buffer.
+	///
+	/// Returns whether there was room for all keys.
+	#[cfg(feature = "net")]
+	pub async fn extend_fetch_jwk_into(&mut self, buffer: &mut [u8]) -> Result<(bool, crate::fetch::Age), FetchExtendError> {
+		let len = crate::fetch::into_jwk(buffer).await?;
+		self.extend_jwk_response(&mut buffer[..len])
+	}
+
+	/// [fetches](crate::fetch::into_jwk_vec) JWK keys and adds them to this set.
+	///
+	/// Returns whether there was room for all keys.
+	#[cfg(feature = "net")]
+	pub async fn extend_fetch_jwk(&mut self) -> Result<(bool, crate::fetch::Age), FetchExtendError> {
+		let mut buffer = Vec::new();
+		crate::fetch::into_jwk_vec(&mut buffer).await?;
+		self.extend_jwk_response(&mut buffer)
+	}
+
+	/// [Fetches](crate::fetch::into_vec) the keys of both the PEM and the
+	/// [JWK](crate::fetch::into_jwk_vec) endpoints, concurrently, and adds them to this set, e.g.
+	/// for when a rotated key shows in one before the other.
+	///
+	/// Keys in both endpoints are added once. Returns whether there was room for all keys, and
+	/// the [shorter-lived](crate::fetch::Age::min_ttl) of the ages.
+	#[cfg(feature = "net")]
+	pub async fn extend_fetch_all(&mut self) -> Result<(bool, crate::fetch::Age), FetchExtendError> {
+		let (mut pem, mut jwk) = (Vec::new(), Vec::new());
+		tokio::try_join!(crate::fetch::into_vec(&mut pem), crate::fetch::into_jwk_vec(&mut jwk))?;
+		self.extend_all_responses(&mut pem, &mut jwk)
+	}
+
+	/// Adds the keys of fetched HTTP PEM and JWK responses to this set, but for the JWK keys whose
+	/// ID is already present.
+	#[cfg(feature = "net")]
+	fn extend_all_responses(&mut self, pem: &mut [u8], jwk: &mut [u8]) -> Result<(bool, crate::fetch::Age), FetchExtendError> {
+		let (pem_fit, pem_age) = self.extend_response(pem)?;
+		let (jwk_fit, jwk_age) = if pem_fit {
+			self.extend_jwk_response_with(jwk, true)?
+		} else {
+			(false, crate::fetch::process_headers(jwk)?.0)
+		};
+		Ok((jwk_fit, crate::fetch::Age::min_ttl(&pem_age, &jwk_age)))
+	}
+
+	/// Adds the keys of a fetched HTTP JWK response to this set.
+	#[cfg(feature = "net")]
+	pub(crate) fn extend_jwk_response(&mut self, response: &mut [u8]) -> Result<(bool, crate::fetch::Age), FetchExtendError> {
+		self.extend_jwk_response_with(response, false)
+	}
+
+	/// Adds the keys of a fetched HTTP JWK response to this set, skipping those whose ID is already
+	/// present if `skip_present`.
+	#[cfg(feature = "net")]
+	fn extend_jwk_response_with(&mut self, response: &mut [u8], skip_present: bool) -> Result<(bool, crate::fetch::Age), FetchExtendError> {
+		let (age, body) = crate::fetch::process_headers(response)?;
+		let mut keys = crate::ParseJwk::new(dechunked(response, body))?;
+		while let Some(key) = keys.next_with_components() {
+			let (id, key, components) = key?;
+			if skip_present && self.contains(id.as_bytes()) { continue; }
+			if !self.push_decoded(id.as_bytes(), key, KeyKind::Rsa, Some(components)) { return Ok((false, age)); }
+		}
+		Ok((true, age))
 	}
 
 	/// Iterates over the keys.
-	pub fn iter(&self) -> impl Iterator<Item = (u64, &DecodingKey)> {
+	pub fn iter(&self) -> Iter<'_> {
+		Iter { keys: self.id[..self.len].iter().zip(self.key[..self.len].iter()) }
+	}
+
+	/// Iterates over the keys with their kinds.
+	pub fn iter_kinds(&self) -> impl Iterator<Item = (u64, &DecodingKey, KeyKind)> {
 		self.id.iter()
 			.zip(self.key.iter())
 			.take(self.len())
-			.map(|(id, key)| unsafe { (id.assume_init(), key.assume_init_ref()) })
+			.map(|(id, key)| unsafe {
+				let key = key.assume_init_ref();
+				(id.assume_init(), &key.key, key.kind)
+			})
+	}
+
+	/// Iterates over the key IDs, e.g. to log which keys are held when a token needs an
+	/// [unknown](ValidateError::UnknownKey) one.
+	///
+	/// IDs that aren't UTF-8 are converted lossily.
+	pub fn key_ids(&self) -> impl Iterator<Item = std::borrow::Cow<'_, str>> {
+		self.stored().map(|key| String::from_utf8_lossy(&key.id))
+	}
+
+	/// Iterates over the keys by their IDs, in the IDs' order rather than insertion order, e.g. for
+	/// reproducible logs or test assertions.
+	pub fn iter_sorted(&self) -> impl Iterator<Item = (&[u8], &DecodingKey)> {
+		let mut keys = self.stored().collect::<Vec<_>>();
+		keys.sort_unstable_by(|a, b| a.id.cmp(&b.id));
+		keys.into_iter().map(|key| (&*key.id, &key.key))
+	}
+
+	/// Iterates over the stored keys.
+	fn stored(&self) -> impl Iterator<Item = &Key> {
+		self.key[..self.len()].iter().map(|key| unsafe { key.assume_init_ref() })
+	}
+
+	/// Renders the keys as a [JWK Set](https://datatracker.ietf.org/doc/html/rfc7517#section-5), e.g.
+	/// to re-expose them.
+	///
+	/// Keys whose components are unknown (see [`push_key_of_kind`](Self::push_key_of_kind)) are left out.
+	pub fn to_jwks_json(&self) -> String {
+		use crate::parse::Components;
+
+		let keys = self.stored()
+			.filter_map(|key| Some(match key.components.as_ref()? {
+				Components::Rsa { n, e } => serde_json::json!({
+					"kty": "RSA", "alg": "RS256", "use": "sig", "kid": String::from_utf8_lossy(&key.id), "n": n, "e": e,
+				}),
+				Components::Ec { crv, x, y } => serde_json::json!({
+					"kty": "EC", "alg": if *crv == "P-384" { "ES384" } else { "ES256" }, "use": "sig",
+					"kid": String::from_utf8_lossy(&key.id), "crv": crv, "x": x, "y": y,
+				}),
+			}))
+			.collect::<Vec<_>>();
+		serde_json::json!({ "keys": keys }).to_string()
 	}
 
 	/// Gets a key by its ID.
 	pub fn get(&self, id: &[u8]) -> Option<&DecodingKey> {
-		let id = hash(id);
-		self.iter()
-			.find(|&(kid,_)| kid == id)
-			.map(|(_, key)| key)
+		self.get_kind(id).map(|(key, _)| key)
+	}
+
+	/// Gets a key and its kind by its ID.
+	///
+	/// IDs are compared by hash first, then in full, so a hash collision can't select the wrong key.
+	pub fn get_kind(&self, id: &[u8]) -> Option<(&DecodingKey, KeyKind)> {
+		let key = unsafe { self.key[self.position(id)?].assume_init_ref() };
+		Some((&key.key, key.kind))
 	}
 
+	/// Gets the index of a key by its ID.
+	fn position(&self, id: &[u8]) -> Option<usize> {
+		let id_hash = hash(id);
+		self.id.iter()
+			.zip(self.stored())
+			.position(|(kid, key)| unsafe { kid.assume_init() } == id_hash && *key.id == *id)
+	}
+
+	/// Takes a [`KeysSnapshot`] of the keys.
+	pub fn snapshot(&self) -> KeysSnapshot {
+		KeysSnapshot {
+			keys: self.id.iter()
+				.zip(self.stored())
+				.map(|(id, key)| (unsafe { id.assume_init() }, key.id.clone(), Arc::new(key.key.clone()), key.kind))
+				.collect(),
+		}
+	}
+
+	/// Gets the key a token claims to be signed with.
+	fn key(&self, token: &str) -> Result<(&DecodingKey, KeyKind), ValidateError> {
+		self.matching(token).map(|key| (&key.key, key.kind))
+	}
+
+	/// Gets the stored key a token's key ID refers to.
+	fn matching(&self, token: &str) -> Result<&Key, ValidateError> {
+		let i = with_kid(token, |kid| self.position(kid))?.ok_or(ValidateError::UnknownKey)?;
+		Ok(unsafe { self.key[i].assume_init_ref() })
+	}
+
+	/// Checks whether there's a key with the given ID.
+	pub fn contains(&self, id: &[u8]) -> bool { self.get(id).is_some() }
+
 	/// Validates a token.
+	///
+	/// Tokens that need a key that isn't present are rejected with [`ValidateError::UnknownKey`]
+	/// right after their header is decoded, before the payload or signature are even looked at.
 	pub fn validate<Claims: serde::de::DeserializeOwned>(&self, token: &str) -> Result<jsonwebtoken::TokenData<Claims>, ValidateError> {
-		let kid = jsonwebtoken::decode_header(token).map_err(ValidateError::DecodeHeader)?.kid.ok_or(ValidateError::TokenMissingKeyId)?;
-		let key = self.get(kid.as_bytes()).ok_or(ValidateError::UnknownKey)?;
-		static VALIDATION: Lazy<jsonwebtoken::Validation> = Lazy::new(|| {
-			let mut validation = jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::RS256);
-			validation.set_issuer(&["accounts.google.com", "https://accounts.google.com"]);
-			validation.validate_aud = false;
+		self.validate_with(token, &VALIDATION)
+	}
+
+	/// Validates a token given as bytes, e.g. straight from an HTTP header buffer.
+	///
+	/// Tokens are ASCII, so bytes that aren't UTF-8 are rejected like any malformed token.
+	pub fn validate_bytes<Claims: serde::de::DeserializeOwned>(&self, token: &[u8]) -> Result<jsonwebtoken::TokenData<Claims>, ValidateError> {
+		let token = std::str::from_utf8(token).map_err(|_| ValidateError::DecodeHeader(ErrorKind::InvalidToken.into()))?;
+		self.validate(token)
+	}
+
+	/// Validates the token of an `Authorization` header value, with or without its `Bearer ` prefix
+	/// (in any case).
+	///
+	/// A value that has the prefix but not a single token after it, e.g. `Bearer` or `Bearer: a.b.c`,
+	/// fails with [`ValidateError::MalformedAuthorization`].
+	pub fn validate_authorization_header<Claims: serde::de::DeserializeOwned>(&self, header_value: &str) -> Result<jsonwebtoken::TokenData<Claims>, ValidateError> {
+		self.validate(bearer_token(header_value)?)
+	}
+
+	/// Validates a token with the given validation rather than the default one, e.g. for other
+	/// issuers or to check the audience.
+	///
+	/// The default validation accepts RS256 (or, for EC keys, ES256) tokens issued by Google (`accounts.google.com` or
+	/// `https://accounts.google.com`), for any audience.
+	pub fn validate_with<Claims: serde::de::DeserializeOwned>(&self, token: &str, validation: &jsonwebtoken::Validation) -> Result<jsonwebtoken::TokenData<Claims>, ValidateError> {
+		let (key, kind) = self.key(token)?;
+		decode(token, key, kind, validation).map_err(|e| decode_error(token, kind, e))
+	}
+
+	/// Validates a token, returning its claims along with its [registered claims](RegisteredClaims)
+	/// and the ID and algorithm of the key that verified it.
+	///
+	/// The registered claims are there even if `Claims` leaves them out, e.g. `Claims` can be just
+	/// the custom claims.
+	///
+	/// The key ID is the one of the stored key that matched, e.g. for logging which key of a
+	/// rotation verified the token.
+	pub fn validate_full<Claims: serde::de::DeserializeOwned>(&self, token: &str) -> Result<ValidatedToken<Claims>, ValidateError> {
+		let key = self.matching(token)?;
+		let jsonwebtoken::TokenData { header, claims } = decode::<serde_json::Value>(token, &key.key, key.kind, &VALIDATION)
+			.map_err(|e| decode_error(token, key.kind, e))?;
+		let registered = serde::Deserialize::deserialize(&claims).map_err(|e| ValidateError::DecodeToken(e.into()))?;
+		let claims = serde::Deserialize::deserialize(claims).map_err(|e| ValidateError::DecodeToken(e.into()))?;
+		Ok(ValidatedToken {
+			claims,
+			registered,
+			kid: String::from_utf8_lossy(&key.id).into_owned(),
+			alg: header.alg,
+		})
+	}
+
+	/// Validates a token and checks the user authenticated (`auth_time` claim) at most `max_auth_age` ago.
+	///
+	/// This is for step-up authentication, where an old session isn't good enough.
+	pub fn validate_auth_age<Claims: serde::de::DeserializeOwned>(&self, token: &str, max_auth_age: std::time::Duration) -> Result<jsonwebtoken::TokenData<Claims>, ValidateError> {
+		let data = self.validate(token)?;
+		#[derive(serde::Deserialize)]
+		struct AuthTime { auth_time: Option<u64> }
+		let auth_time = insecure_claims::<AuthTime>(token).map_err(ValidateError::DecodeToken)?
+			.auth_time.ok_or(ValidateError::MissingAuthTime)?;
+		if jsonwebtoken::get_current_timestamp().saturating_sub(auth_time) > max_auth_age.as_secs() {
+			return Err(ValidateError::AuthTooOld);
+		}
+		Ok(data)
+	}
+
+	/// Validates a token into a [`ValidationDecision`], for authorization layers.
+	///
+	/// An invalid token isn't an error here, it's a decision with a [`reason`](ValidationDecision::reason).
+	pub fn validate_decision(&self, token: &str) -> ValidationDecision {
+		#[derive(serde::Deserialize)]
+		struct Fields { sub: Option<String>, email: Option<String>, exp: Option<u64> }
+		match self.validate::<Fields>(token) {
+			Ok(data) => ValidationDecision {
+				valid: true,
+				reason: None,
+				sub: data.claims.sub,
+				email: data.claims.email,
+				exp: data.claims.exp,
+			},
+			Err(e) => ValidationDecision { valid: false, reason: Some(e), sub: None, email: None, exp: None },
+		}
+	}
+
+	/// Validates a Google Workspace user's token into a [`WorkspaceUser`].
+	///
+	/// The email must be verified (`email_verified` claim) and the token must have a hosted domain
+	/// (`hd` claim), which consumer accounts' tokens don't.
+	pub fn validate_workspace(&self, token: &str) -> Result<WorkspaceUser, ValidateError> {
+		#[derive(serde::Deserialize)]
+		struct Fields { sub: String, email: String, email_verified: Option<bool>, hd: Option<String> }
+		let claims = self.validate::<Fields>(token)?.claims;
+		if claims.email_verified != Some(true) { return Err(ValidateError::EmailNotVerified); }
+		let hd = claims.hd.ok_or(ValidateError::MissingHostedDomain)?;
+		Ok(WorkspaceUser { sub: claims.sub, email: claims.email, email_verified: true, hd })
+	}
+
+	/// Validates a token and checks it was issued for one of the audiences (`aud` claim), e.g. your
+	/// OAuth client ID.
+	///
+	/// Any of the audiences will do, so apps with several client IDs (e.g. web, iOS, and Android)
+	/// pass them all. A token that isn't for any of them fails with [`ValidateError::Audience`].
+	pub fn validate_aud<Claims: serde::de::DeserializeOwned>(&self, token: &str, audiences: &[&str]) -> Result<jsonwebtoken::TokenData<Claims>, ValidateError> {
+		let mut validation = VALIDATION.clone();
+		validation.set_audience(audiences);
+		validation.validate_aud = true;
+		validation.required_spec_claims.insert("aud".to_owned());
+		self.validate_with(token, &validation).map_err(|e| match e {
+			ValidateError::DecodeToken(e) if match e.kind() {
+				ErrorKind::InvalidAudience => true,
+				ErrorKind::MissingRequiredClaim(claim) => claim == "aud",
+				_ => false,
+			} => ValidateError::Audience,
+			e => e,
+		})
+	}
+
+	/// Validates a token with the [`ValidationBuilder`]'s validation.
+	///
+	/// Tokens that aren't valid yet (see [`ValidationBuilder::not_before`] and
+	/// [`ValidationBuilder::reject_future_iat`]) fail with [`ValidateError::NotYetValid`].
+	pub fn validate_with_builder<Claims: serde::de::DeserializeOwned>(&self, token: &str, builder: &ValidationBuilder) -> Result<jsonwebtoken::TokenData<Claims>, ValidateError> {
+		let data = self.validate_with(token, &builder.validation).map_err(|e| match e {
+			ValidateError::DecodeToken(e) if *e.kind() == ErrorKind::ImmatureSignature => ValidateError::NotYetValid,
+			e => e,
+		})?;
+		if builder.reject_future_iat {
+			#[derive(serde::Deserialize)]
+			struct IssuedAt { iat: Option<u64> }
+			let iat = insecure_claims::<IssuedAt>(token).map_err(ValidateError::DecodeToken)?.iat;
+			if iat.is_some_and(|iat| iat > jsonwebtoken::get_current_timestamp().saturating_add(builder.validation.leeway)) {
+				return Err(ValidateError::NotYetValid);
+			}
+		}
+		Ok(data)
+	}
+
+	/// Validates a token, accepting only the given algorithms for this call.
+	pub fn validate_with_algorithms<Claims: serde::de::DeserializeOwned>(&self, token: &str, algs: &[jsonwebtoken::Algorithm]) -> Result<jsonwebtoken::TokenData<Claims>, ValidateError> {
+		let mut validation = VALIDATION.clone();
+		validation.algorithms = algs.to_vec();
+		self.validate_with(token, &validation)
+	}
+
+	/// Validates a token, accepting the issuers (`iss` claim) that satisfy the predicate instead of Google's.
+	///
+	/// This is for issuer rules a fixed set can't express, e.g. any Firebase project's
+	/// `https://securetoken.google.com/<project>`.
+	pub fn validate_with_issuer_fn<Claims: serde::de::DeserializeOwned>(&self, token: &str, pred: impl Fn(&str) -> bool) -> Result<jsonwebtoken::TokenData<Claims>, ValidateError> {
+		static ANY_ISSUER: Lazy<jsonwebtoken::Validation> = Lazy::new(|| {
+			let mut validation = VALIDATION.clone();
+			validation.iss = None;
 			validation
 		});
-		jsonwebtoken::decode(token, key, &VALIDATION).map_err(ValidateError::DecodeToken)
+		let data = self.validate_with(token, &ANY_ISSUER)?;
+		#[derive(serde::Deserialize)]
+		struct Issuer { iss: Option<String> }
+		let issuer = insecure_claims::<Issuer>(token).map_err(ValidateError::DecodeToken)?;
+		if !issuer.iss.is_some_and(|iss| pred(&iss)) {
+			return Err(ValidateError::InvalidIssuer);
+		}
+		Ok(data)
+	}
+
+	/// Validates a token, falling back to trying every key if it doesn't have a known key ID.
+	///
+	/// Tokens with a known key ID are validated against that key only, same as
+	/// [`validate`](Self::validate). Otherwise, the first key that validates the token wins, and if
+	/// none does, every key's error is reported in [`ValidateError::AllKeysFailed`].
+	pub fn validate_any_key<Claims: serde::de::DeserializeOwned>(&self, token: &str) -> Result<jsonwebtoken::TokenData<Claims>, ValidateError> {
+		let header = jsonwebtoken::decode_header(token).map_err(ValidateError::DecodeHeader)?;
+		if let Some((key, kind)) = header.kid.and_then(|kid| self.get_kind(kid.as_bytes())) {
+			return decode(token, key, kind, &VALIDATION).map_err(|e| decode_error(token, kind, e));
+		}
+		if self.is_empty() { return Err(ValidateError::UnknownKey); }
+		let mut errors = Vec::with_capacity(self.len());
+		for (id, key, kind) in self.iter_kinds() {
+			match decode(token, key, kind, &VALIDATION) {
+				Ok(data) => return Ok(data),
+				Err(e) => errors.push((id, e)),
+			}
+		}
+		Err(ValidateError::AllKeysFailed(errors))
+	}
+
+	/// Validates a token, tolerating `leeway` seconds of clock skew on `exp`/`nbf`.
+	///
+	/// The other validations use jsonwebtoken's default [`leeway`](jsonwebtoken::Validation::leeway) of 60 seconds.
+	pub fn validate_with_leeway<Claims: serde::de::DeserializeOwned>(&self, token: &str, leeway: u64) -> Result<jsonwebtoken::TokenData<Claims>, ValidateError> {
+		let mut validation = VALIDATION.clone();
+		validation.leeway = leeway;
+		self.validate_with(token, &validation)
+	}
+
+	/// Validates a token as if the current time was `offset` seconds off the local clock.
+	///
+	/// This is for nodes with a known clock skew: a node whose clock lags 30 seconds behind the
+	/// issuer's should pass `30`. Unlike [`leeway`](jsonwebtoken::Validation::leeway), which widens
+	/// the `exp`/`nbf` window in both directions, this shifts it.
+	pub fn validate_with_clock_offset<Claims: serde::de::DeserializeOwned>(&self, token: &str, offset: i64) -> Result<jsonwebtoken::TokenData<Claims>, ValidateError> {
+		let now = jsonwebtoken::get_current_timestamp().saturating_add_signed(offset);
+		self.validate_at_timestamp(token, &VALIDATION, now)
+	}
+
+	/// Validates a token, checking `exp`/`nbf` as of `now` rather than the current time, e.g. to
+	/// validate a fixed token in tests.
+	pub fn validate_at<Claims: serde::de::DeserializeOwned>(&self, token: &str, now: std::time::SystemTime) -> Result<jsonwebtoken::TokenData<Claims>, ValidateError> {
+		let now = now.duration_since(std::time::UNIX_EPOCH).map_or(0, |now| now.as_secs());
+		self.validate_at_timestamp(token, &VALIDATION, now)
+	}
+
+	/// Validates a token and gets one of its claims, e.g. the `email` to route a request by,
+	/// without deserializing the others.
+	///
+	/// Returns `None` if the token doesn't have the claim.
+	pub fn validate_claim(&self, token: &str, claim: &str) -> Result<Option<serde_json::Value>, ValidateError> {
+		self.validate::<serde::de::IgnoredAny>(token)?;
+		self::claim(token, claim).map_err(ValidateError::DecodeToken)
+	}
+
+	/// Validates an ID token and verifies its `at_hash` claim against the access token issued with it.
+	///
+	/// See [OpenID Connect Core § 3.2.2.9](https://openid.net/specs/openid-connect-core-1_0.html#ImplicitTokenValidation).
+	pub fn verify_at_hash<Claims: serde::de::DeserializeOwned>(&self, token: &str, access_token: &str) -> Result<jsonwebtoken::TokenData<Claims>, ValidateError> {
+		let data = self.validate(token)?;
+		let hashes = insecure_claims::<TokenHashes>(token).map_err(ValidateError::DecodeToken)?;
+		if hashes.at_hash.is_none() || hashes.at_hash != token_hash(data.header.alg, access_token) {
+			return Err(ValidateError::AtHashMismatch);
+		}
+		Ok(data)
+	}
+
+	/// Validates an ID token and verifies its `c_hash` claim against the authorization code issued with it.
+	///
+	/// See [OpenID Connect Core § 3.3.2.11](https://openid.net/specs/openid-connect-core-1_0.html#HybridIDToken).
+	pub fn verify_c_hash<Claims: serde::de::DeserializeOwned>(&self, token: &str, code: &str) -> Result<jsonwebtoken::TokenData<Claims>, ValidateError> {
+		let data = self.validate(token)?;
+		let hashes = insecure_claims::<TokenHashes>(token).map_err(ValidateError::DecodeToken)?;
+		if hashes.c_hash.is_none() || hashes.c_hash != token_hash(data.header.alg, code) {
+			return Err(ValidateError::CHashMismatch);
+		}
+		Ok(data)
+	}
+
+	/// Decodes a token's header **without verifying anything**, e.g. to log the `kid` and `alg` of a
+	/// rejected token.
+	pub fn peek_header(token: &str) -> Result<jsonwebtoken::Header, ValidateError> {
+		jsonwebtoken::decode_header(token).map_err(ValidateError::DecodeHeader)
+	}
+
+	/// Decodes a token's claims **without verifying anything**, neither the signature nor any claim.
+	///
+	/// This is for debugging, e.g. to log the issuer and expiry of a rejected token; never trust its output.
+	pub fn peek_claims_unverified<Claims: serde::de::DeserializeOwned>(token: &str) -> Result<Claims, ValidateError> {
+		insecure_claims(token).map_err(ValidateError::DecodeToken)
+	}
+
+	/// Validates a token, checking `exp`/`nbf` against the given UNIX timestamp rather than the clock.
+	fn validate_at_timestamp<Claims: serde::de::DeserializeOwned>(&self, token: &str, validation: &jsonwebtoken::Validation, now: u64) -> Result<jsonwebtoken::TokenData<Claims>, ValidateError> {
+		let mut untimed = validation.clone();
+		untimed.validate_exp = false;
+		untimed.validate_nbf = false;
+		let data = self.validate_with(token, &untimed)?;
+
+		#[derive(serde::Deserialize)]
+		struct Times { exp: Option<u64>, nbf: Option<u64> }
+		let times = insecure_claims::<Times>(token).map_err(ValidateError::DecodeToken)?;
+		if validation.validate_exp && times.exp.is_some_and(|exp| exp < now.saturating_sub(validation.leeway)) {
+			return Err(ValidateError::DecodeToken(ErrorKind::ExpiredSignature.into()));
+		}
+		if validation.validate_nbf && times.nbf.is_some_and(|nbf| nbf > now.saturating_add(validation.leeway)) {
+			return Err(ValidateError::DecodeToken(ErrorKind::ImmatureSignature.into()));
+		}
+		Ok(data)
+	}
+}
+
+/// Gets the body of a fetched HTTP response, [dechunking](crate::fetch::dechunk) it if needed.
+#[cfg(feature = "net")]
+fn dechunked(response: &mut [u8], body: usize) -> &mut [u8] {
+	if crate::fetch::is_chunked(response) {
+		let len = crate::fetch::dechunk(&mut response[body..]);
+		&mut response[body..body + len]
+	} else {
+		&mut response[body..]
 	}
 }
 
+/// A read-only snapshot of [`Keys`] that is cheap to clone.
+///
+/// See [`Keys::snapshot`].
+#[derive(Clone, Default)]
+pub struct KeysSnapshot {
+	keys: Box<[SnapshotKey]>,
+}
+
+/// A [`KeysSnapshot`] key: its ID hash, ID, key and kind.
+type SnapshotKey = (u64, Box<[u8]>, Arc<DecodingKey>, KeyKind);
+
+impl KeysSnapshot {
+	/// Gets the count of keys.
+	pub fn len(&self) -> usize { self.keys.len() }
+	/// Gets whether there are no keys.
+	pub fn is_empty(&self) -> bool { self.keys.is_empty() }
+
+	/// Iterates over the keys.
+	pub fn iter(&self) -> impl Iterator<Item = (u64, &Arc<DecodingKey>)> {
+		self.keys.iter().map(|(id, _, key, _)| (*id, key))
+	}
+
+	/// Gets a key by its ID.
+	pub fn get(&self, id: &[u8]) -> Option<&Arc<DecodingKey>> {
+		self.get_kind(id).map(|(key, _)| key)
+	}
+
+	/// Gets a key and its kind by its ID.
+	pub fn get_kind(&self, id: &[u8]) -> Option<(&Arc<DecodingKey>, KeyKind)> {
+		let id_hash = hash(id);
+		self.keys.iter()
+			.find(|(kid_hash, kid, ..)| *kid_hash == id_hash && **kid == *id)
+			.map(|(_, _, key, kind)| (key, *kind))
+	}
+
+	/// Validates a token.
+	pub fn validate<Claims: serde::de::DeserializeOwned>(&self, token: &str) -> Result<jsonwebtoken::TokenData<Claims>, ValidateError> {
+		let (key, kind) = with_kid(token, |kid| self.get_kind(kid))?.ok_or(ValidateError::UnknownKey)?;
+		decode(token, key, kind, &VALIDATION).map_err(|e| decode_error(token, kind, e))
+	}
+}
+
+/// The outcome of [`Keys::validate_decision`].
+///
+/// The claims are only extracted from valid tokens.
+#[derive(Debug)]
+pub struct ValidationDecision {
+	/// Whether the token is valid.
+	pub valid: bool,
+	/// Why the token is invalid.
+	pub reason: Option<ValidateError>,
+	/// The subject (`sub` claim).
+	pub sub: Option<String>,
+	/// The `email` claim.
+	pub email: Option<String>,
+	/// The expiration UNIX time (`exp` claim).
+	pub exp: Option<u64>,
+}
+
+/// Builds a [`Validation`](jsonwebtoken::Validation) for [`Keys::validate_with_builder`] /
+/// [`Keys::validate_with`].
+///
+/// It starts from the validation [`Keys::validate`] uses.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationBuilder {
+	validation: jsonwebtoken::Validation,
+	reject_future_iat: bool,
+}
+
+impl Default for ValidationBuilder {
+	fn default() -> Self { Self { validation: VALIDATION.clone(), reject_future_iat: false } }
+}
+
+impl ValidationBuilder {
+	/// Creates a builder of the validation [`Keys::validate`] uses.
+	pub fn new() -> Self { Self::default() }
+
+	/// Sets the accepted issuers (`iss` claim).
+	pub fn issuers(mut self, issuers: &[&str]) -> Self {
+		self.validation.set_issuer(issuers);
+		self
+	}
+
+	/// Sets the accepted audiences (`aud` claim), which tokens are then required to have.
+	pub fn audiences(mut self, audiences: &[&str]) -> Self {
+		self.validation.set_audience(audiences);
+		self.validation.validate_aud = true;
+		self.validation.required_spec_claims.insert("aud".to_owned());
+		self
+	}
+
+	/// Sets the leeway for the `exp` and `nbf` claims, in seconds.
+	pub fn leeway(mut self, leeway: u64) -> Self {
+		self.validation.leeway = leeway;
+		self
+	}
+
+	/// Sets the accepted algorithms.
+	pub fn algorithms(mut self, algorithms: &[Algorithm]) -> Self {
+		self.validation.algorithms = algorithms.to_vec();
+		self
+	}
+
+	/// Sets whether to reject tokens whose `nbf` claim is in the future (off by default).
+	pub fn not_before(mut self, validate: bool) -> Self {
+		self.validation.validate_nbf = validate;
+		self
+	}
+
+	/// Sets whether to reject tokens whose `iat` claim is in the future (off by default).
+	///
+	/// The check is [`Keys::validate_with_builder`]'s, so it isn't part of the [built](Self::build)
+	/// validation.
+	pub fn reject_future_iat(mut self, reject: bool) -> Self {
+		self.reject_future_iat = reject;
+		self
+	}
+
+	/// Builds the validation.
+	pub fn build(self) -> jsonwebtoken::Validation { self.validation }
+}
+
+/// The IDs of the keys there was no room for.
+///
+/// See [`Keys::try_extend_dropped`].
+pub type DroppedIds = Vec<Box<[u8]>>;
+
+/// The user of a Google Workspace token.
+///
+/// See [`Keys::validate_workspace`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkspaceUser {
+	/// The subject (`sub` claim).
+	pub sub: String,
+	/// The `email` claim.
+	pub email: String,
+	/// The `email_verified` claim.
+	pub email_verified: bool,
+	/// The hosted domain (`hd` claim).
+	pub hd: String,
+}
+
+/// A token validated by [`Keys::validate_full`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidatedToken<Claims> {
+	/// The claims.
+	pub claims: Claims,
+	/// The registered claims.
+	pub registered: RegisteredClaims,
+	/// The ID of the key that verified the token.
+	pub kid: String,
+	/// The algorithm the token was verified with.
+	pub alg: Algorithm,
+}
+
+/// The registered claims of a Google token.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+pub struct RegisteredClaims {
+	/// The issuer (`iss` claim).
+	pub iss: String,
+	/// The subject (`sub` claim).
+	pub sub: Option<String>,
+	/// The expiration UNIX time (`exp` claim).
+	pub exp: u64,
+	/// The issuance UNIX time (`iat` claim).
+	pub iat: Option<u64>,
+	/// The not-before UNIX time (`nbf` claim).
+	pub nbf: Option<u64>,
+	/// The audiences (`aud` claim), which is either one or several.
+	#[serde(default, deserialize_with = "audiences")]
+	pub aud: Vec<String>,
+}
+
+/// Deserializes an `aud` claim, which is either a string or an array of them.
+fn audiences<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+	#[derive(serde::Deserialize)]
+	#[serde(untagged)]
+	enum Audiences { One(String), Many(Vec<String>) }
+	Ok(match serde::Deserialize::deserialize(deserializer)? {
+		Audiences::One(aud) => vec![aud],
+		Audiences::Many(aud) => aud,
+	})
+}
+
+/// Validates a token against the keys of a certs endpoint body, e.g. one fetched with your own
+/// HTTP client.
+///
+/// The body is parsed in place into a temporary [`Keys`]; to validate several tokens, keep a
+/// [`Keys::from_certs_json`] around instead.
+pub fn validate_from_body<Claims: serde::de::DeserializeOwned>(certs_body: &mut [u8], token: &str) -> Result<jsonwebtoken::TokenData<Claims>, ValidateFromBodyError> {
+	Ok(Keys::<KEYS_CAPACITY>::from_certs_json(certs_body)?.validate(token)?)
+}
+
+/// Decodes a single RSA or EC PEM (a certificate or a public key) into a key, along with its kind.
+///
+/// This is the conversion [`Keys::push`] does, for keys that are used on their own.
+pub fn decode_pem(pem: &[u8]) -> Result<(DecodingKey, KeyKind), jsonwebtoken::errors::Error> {
+	match DecodingKey::from_rsa_pem(pem) {
+		Ok(key) => Ok((key, KeyKind::Rsa)),
+		Err(e) => Ok((DecodingKey::from_ec_pem(pem).map_err(|_| e)?, KeyKind::Ec)),
+	}
+}
+
+/// Gets the token of an `Authorization` header value; see [`Keys::validate_authorization_header`].
+fn bearer_token(header_value: &str) -> Result<&str, ValidateError> {
+	const BEARER: &str = "bearer";
+	let value = header_value.trim();
+	let Some(prefix) = value.get(..BEARER.len()).filter(|prefix| prefix.eq_ignore_ascii_case(BEARER)) else { return Ok(value) };
+	let rest = &value[prefix.len()..];
+	let token = rest.trim_start();
+	if token.len() == rest.len() || token.is_empty() || token.contains(char::is_whitespace) {
+		return Err(ValidateError::MalformedAuthorization);
+	}
+	Ok(token)
+}
+
+/// Calls the function with the key ID of a token.
+///
+/// Typical headers are decoded on the stack, without allocating; the others (large, nested, or
+/// escaped ones) go through [`jsonwebtoken::decode_header`].
+fn with_kid<T>(token: &str, f: impl FnOnce(&[u8]) -> T) -> Result<T, ValidateError> {
+	let mut buffer = [0u8; 192];
+	if let Some(kid) = fast_kid(token, &mut buffer) { return Ok(f(kid)); }
+	let kid = jsonwebtoken::decode_header(token).map_err(ValidateError::DecodeHeader)?.kid.ok_or(ValidateError::TokenMissingKeyId)?;
+	Ok(f(kid.as_bytes()))
+}
+
+/// Gets a claim of a token, skipping over the others.
+fn claim(token: &str, name: &str) -> Result<Option<serde_json::Value>, jsonwebtoken::errors::Error> {
+	use base64::Engine;
+	use serde::de::{Deserializer, IgnoredAny, MapAccess, Visitor};
+
+	struct Claim<'n>(&'n str);
+	impl<'de> Visitor<'de> for Claim<'_> {
+		type Value = Option<serde_json::Value>;
+
+		fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result { f.write_str("the claims object") }
+
+		fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+			let mut value = None;
+			while let Some(name) = map.next_key::<std::borrow::Cow<str>>()? {
+				if value.is_none() && name == self.0 { value = Some(map.next_value()?); }
+				else { map.next_value::<IgnoredAny>()?; }
+			}
+			Ok(value)
+		}
+	}
+
+	let payload = token.split('.').nth(1).ok_or(ErrorKind::InvalidToken)?;
+	let payload = base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(payload).map_err(|_| ErrorKind::InvalidToken)?;
+	Ok(serde_json::Deserializer::from_slice(&payload).deserialize_map(Claim(name))?)
+}
+
+/// Gets the key ID of a token whose header is a small flat JSON object with exactly one, unescaped,
+/// `kid` member.
+///
+/// Returns `None` for anything else, which is for [`jsonwebtoken::decode_header`] to sort out.
+fn fast_kid<'b>(token: &str, buffer: &'b mut [u8]) -> Option<&'b [u8]> {
+	use base64::Engine;
+	let header = token.as_bytes().split(|&c| c == b'.').next()?;
+	let len = base64::engine::general_purpose::URL_SAFE_NO_PAD.decode_slice(header, buffer).ok()?;
+	let header = &buffer[..len];
+	if memchr::memchr_iter(b'{', header).count() != 1 || memchr::memchr(b'[', header).is_some() { return None; }
+	let mut kids = memchr::memmem::find_iter(header, b"\"kid\"");
+	let kid = kids.next()? + b"\"kid\"".len();
+	if kids.next().is_some() { return None; }
+	let rest = header[kid..].trim_ascii_start().strip_prefix(b":")?.trim_ascii_start().strip_prefix(b"\"")?;
+	let end = memchr::memchr2(b'"', b'\\', rest)?;
+	(rest[end] == b'"').then(|| &rest[..end])
+}
+
+/// The validation for Google-issued tokens: RS256 for RSA keys, ES256 for EC keys.
+static VALIDATION: Lazy<jsonwebtoken::Validation> = Lazy::new(|| {
+	let mut validation = jsonwebtoken::Validation::new(Algorithm::RS256);
+	validation.algorithms.push(Algorithm::ES256);
+	validation.set_issuer(&["accounts.google.com", "https://accounts.google.com"]);
+	validation.validate_aud = false;
+	validation
+});
+
+/// [`VALIDATION`] [narrowed](decode) to each [`KeyKind`].
+static VALIDATION_BY_KIND: Lazy<[jsonwebtoken::Validation; 2]> = Lazy::new(|| {
+	[KeyKind::Rsa, KeyKind::Ec].map(|kind| {
+		let mut validation = VALIDATION.clone();
+		validation.algorithms.retain(|alg| kind.algorithms().contains(alg));
+		validation
+	})
+});
+
+/// Decodes a token with a key, accepting only the validation's algorithms that are of the key's kind.
+fn decode<Claims: serde::de::DeserializeOwned>(token: &str, key: &DecodingKey, kind: KeyKind, validation: &jsonwebtoken::Validation) -> Result<jsonwebtoken::TokenData<Claims>, jsonwebtoken::errors::Error> {
+	// The default validation is narrowed ahead of time, as it's the common case.
+	if std::ptr::eq(validation, &*VALIDATION) {
+		return jsonwebtoken::decode(token, key, &VALIDATION_BY_KIND[kind as usize]);
+	}
+	if validation.algorithms.iter().all(|alg| kind.algorithms().contains(alg)) {
+		return jsonwebtoken::decode(token, key, validation);
+	}
+	let mut narrowed = validation.clone();
+	narrowed.algorithms.retain(|alg| kind.algorithms().contains(alg));
+	if narrowed.algorithms.is_empty() { return Err(ErrorKind::InvalidAlgorithm.into()); }
+	jsonwebtoken::decode(token, key, &narrowed)
+}
+
+/// Converts a [`decode`] error, telling a token whose algorithm isn't of its key's kind (e.g. an
+/// ES256 token for an RSA key) by [`ValidateError::AlgorithmMismatch`].
+fn decode_error(token: &str, kind: KeyKind, error: jsonwebtoken::errors::Error) -> ValidateError {
+	// The header is decoded again only on this error path, so valid tokens don't pay for the check.
+	if *error.kind() == ErrorKind::InvalidAlgorithm
+		&& jsonwebtoken::decode_header(token).is_ok_and(|header| !kind.algorithms().contains(&header.alg)) {
+		return ValidateError::AlgorithmMismatch;
+	}
+	ValidateError::DecodeToken(error)
+}
+
+/// The OpenID Connect token hash claims.
+#[derive(serde::Deserialize)]
+struct TokenHashes {
+	at_hash: Option<String>,
+	c_hash: Option<String>,
+}
+
+/// Computes an OpenID Connect token hash (`at_hash` / `c_hash`) of the value, for a token signed with `alg`.
+///
+/// That is the base64url encoding of the left half of the value's hash, using the hash function of `alg`.
+fn token_hash(alg: jsonwebtoken::Algorithm, value: &str) -> Option<String> {
+	use jsonwebtoken::Algorithm::*;
+	use base64::Engine;
+	let digest = match alg {
+		HS256 | RS256 | PS256 | ES256 => &ring::digest::SHA256,
+		HS384 | RS384 | PS384 | ES384 => &ring::digest::SHA384,
+		HS512 | RS512 | PS512 => &ring::digest::SHA512,
+		EdDSA => return None,
+	};
+	let hash = ring::digest::digest(digest, value.as_bytes());
+	let hash = hash.as_ref();
+	Some(base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(&hash[..hash.len() / 2]))
+}
+
+/// Decodes a token's claims without verifying anything.
+///
+/// Only use this on tokens that were (or are about to be) verified.
+fn insecure_claims<Claims: serde::de::DeserializeOwned>(token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
+	static INSECURE: Lazy<jsonwebtoken::Validation> = Lazy::new(|| {
+		let mut validation = jsonwebtoken::Validation::default();
+		validation.insecure_disable_signature_validation();
+		validation.validate_exp = false;
+		validation.validate_aud = false;
+		validation.required_spec_claims.clear();
+		validation
+	});
+	jsonwebtoken::decode(token, &DecodingKey::from_secret(&[]), &INSECURE).map(|data| data.claims)
+}
+
 /// [`Keys::validate`] error.
 #[derive(Debug, thiserror::Error)]
 pub enum ValidateError {
@@ -126,9 +1152,63 @@ pub enum ValidateError {
 	DecodeToken(jsonwebtoken::errors::Error),
 	#[error("token needs an unknown key ID")]
 	UnknownKey,
+	#[error("the token has no auth_time")]
+	MissingAuthTime,
+	#[error("the user authenticated too long ago")]
+	AuthTooOld,
+	#[error("the token's issuer was rejected")]
+	InvalidIssuer,
+	#[error("the token isn't for the audience")]
+	Audience,
+	#[error("no key validated the token: {0:?}")]
+	AllKeysFailed(Vec<(u64, jsonwebtoken::errors::Error)>),
+	#[error("the token's at_hash doesn't match the access token")]
+	AtHashMismatch,
+	#[error("the token's c_hash doesn't match the authorization code")]
+	CHashMismatch,
+	#[error("the token's email isn't verified")]
+	EmailNotVerified,
+	#[error("the token has no hosted domain")]
+	MissingHostedDomain,
+	#[error("the token isn't valid yet")]
+	NotYetValid,
+	#[error("the token's algorithm isn't of its key's type")]
+	AlgorithmMismatch,
+	#[error("the Authorization header isn't a bearer token")]
+	MalformedAuthorization,
+}
+
+/// [`Keys::from_env`] error.
+#[derive(Debug, thiserror::Error)]
+pub enum FromEnvError {
+	#[error("failed to read the environment variable: {0}")]
+	Var(#[from] std::env::VarError),
+	#[error("JWT error: {0}")]
+	Jwt(#[from] jsonwebtoken::errors::Error),
+	#[error("there's no room for all the keys")]
+	Capacity,
+}
+
+/// [`Keys::from_certs_json`] error.
+#[derive(Debug, thiserror::Error)]
+pub enum FromCertsJsonError {
+	#[error("JWT error: {0}")]
+	Jwt(#[from] jsonwebtoken::errors::Error),
+	#[error("there's no room for all the keys")]
+	Capacity,
+}
+
+/// [`validate_from_body`] error.
+#[derive(Debug, thiserror::Error)]
+pub enum ValidateFromBodyError {
+	#[error("failed to load the keys: {0}")]
+	Keys(#[from] FromCertsJsonError),
+	#[error("failed to validate the token: {0}")]
+	Validate(#[from] ValidateError),
 }
 
 /// [`Keys::extend_fetch`] / [`Keys::extend_fetch_into`] error.
+#[cfg(feature = "cache")]
 #[derive(Debug, thiserror::Error)]
 pub enum FetchExtendError {
 	#[error("fetch error: {0}")]
@@ -137,6 +1217,8 @@ pub enum FetchExtendError {
 	HttpProcess(#[from] crate::fetch::ErrorProcess),
 	#[error("JWT error: {0}")]
 	Jwt(#[from] jsonwebtoken::errors::Error),
+	#[error("JWK Set parse error: {0}")]
+	Json(#[from] serde_json::Error),
 }
 
 fn hash(a: &[u8]) -> u64 {
@@ -144,3 +1226,705 @@ fn hash(a: &[u8]) -> u64 {
 	a.hash(&mut hasher);
 	hasher.finish()
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::testing;
+
+	#[test]
+	fn test_validate() {
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &testing::claims(3600));
+		testing::keys().validate::<serde_json::Value>(&token).unwrap();
+	}
+
+	#[cfg(feature = "net")]
+	#[test]
+	fn test_extend_response_ignores_stale_buffer() {
+		let previous = b"HTTP/1.0 200 OK\r\nCache-Control: public, max-age=22270\r\nAge: 9\r\n\r\n{}";
+		let current = b"HTTP/1.0 200 OK\r\nCache-Control: public, max-age=100\r\n\r\n{}";
+		let mut buffer = [0u8; 256];
+		buffer[..previous.len()].copy_from_slice(previous);
+		buffer[..current.len()].copy_from_slice(current);
+		let (_, age) = Keys::with_default_capacity().extend_response(&mut buffer[..current.len()]).unwrap();
+		assert_eq!(age, crate::fetch::Age { age: 0, max_age: 100 });
+	}
+
+	#[test]
+	fn test_capacity() {
+		let mut small = Keys::<1>::new();
+		assert!(small.push(b"a", testing::RSA_A_CERT.as_bytes()).unwrap());
+		assert!(!small.push(b"b", testing::RSA_A_CERT.as_bytes()).unwrap());
+		let mut large = Keys::<8>::new();
+		let ids = (0..8).map(|i| format!("k{i}")).collect::<Vec<_>>();
+		let mut body = testing::certs_body(ids.iter().map(|id| (id.as_str(), testing::RSA_A_CERT)));
+		assert!(large.try_extend(crate::parse(&mut body)).unwrap());
+		assert_eq!(large.len(), 8);
+	}
+
+	#[cfg(feature = "net")]
+	#[test]
+	fn test_extend_jwk_response() {
+		let response = format!(
+			"HTTP/1.0 200 OK\r\nCache-Control: public, max-age=100\r\n\r\n{{\"keys\":[{{\"kty\":\"RSA\",\"kid\":\"a\",\"n\":\"{}\",\"e\":\"{}\"}}]}}",
+			testing::RSA_A_N, testing::RSA_A_E,
+		);
+		let mut keys = Keys::with_default_capacity();
+		let (all_fit, age) = keys.extend_jwk_response(&mut response.into_bytes()).unwrap();
+		assert!(all_fit);
+		assert_eq!(age.max_age, 100);
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &testing::claims(3600));
+		keys.validate::<serde_json::Value>(&token).unwrap();
+	}
+
+	#[cfg(feature = "net")]
+	#[test]
+	fn test_extend_all_responses() {
+		let jwk = format!(
+			"HTTP/1.0 200 OK\r\nCache-Control: public, max-age=100\r\n\r\n{{\"keys\":[{{\"kty\":\"RSA\",\"kid\":\"a\",\"n\":\"{}\",\"e\":\"{}\"}}]}}",
+			testing::RSA_A_N, testing::RSA_A_E,
+		);
+		let pem = |certs: &[(&str, &str)]| [&b"HTTP/1.0 200 OK\r\nCache-Control: public, max-age=200\r\n\r\n"[..], &testing::certs_body(certs.iter().copied())].concat();
+
+		let mut keys = Keys::with_default_capacity();
+		let (all_fit, age) = keys.extend_all_responses(&mut pem(&[("b", testing::RSA_B_CERT)]), &mut jwk.clone().into_bytes()).unwrap();
+		assert_eq!((all_fit, age.max_age), (true, 100));
+		assert_eq!(keys.key_ids().collect::<Vec<_>>(), ["b", "a"]);
+
+		let mut keys = Keys::with_default_capacity();
+		keys.extend_all_responses(&mut pem(&[("a", testing::RSA_A_CERT)]), &mut jwk.clone().into_bytes()).unwrap();
+		assert_eq!(keys.len(), 1);
+
+		// The PEM keys don't fit, but the age is still the nearer one.
+		let mut keys = Keys::<1>::new();
+		let (all_fit, age) = keys.extend_all_responses(&mut pem(&[("a", testing::RSA_A_CERT), ("b", testing::RSA_B_CERT)]), &mut jwk.clone().into_bytes()).unwrap();
+		assert_eq!((all_fit, age.max_age), (false, 100));
+
+		// The JWK path on its own doesn't skip present keys.
+		let mut keys = Keys::with_default_capacity();
+		keys.extend_jwk_response(&mut jwk.clone().into_bytes()).unwrap();
+		keys.extend_jwk_response(&mut jwk.into_bytes()).unwrap();
+		assert_eq!(keys.len(), 2);
+	}
+
+	#[test]
+	fn test_try_extend_stops_at_capacity() {
+		let ids = (0..100).map(|i| format!("k{i}")).collect::<Vec<_>>();
+		// Only the keys that fit are valid, so parsing any other would fail.
+		let mut body = testing::certs_body(ids.iter().enumerate().map(|(i, id)| {
+			(id.as_str(), if i < KEYS_CAPACITY { testing::RSA_A_CERT } else { "garbage" })
+		}));
+		let mut keys = Keys::with_default_capacity();
+		assert!(!keys.try_extend(crate::parse(&mut body)).unwrap());
+		assert_eq!(keys.len(), KEYS_CAPACITY);
+		assert!(keys.contains(b"k0"));
+		assert!(!keys.contains(format!("k{KEYS_CAPACITY}").as_bytes()));
+	}
+
+	#[test]
+	fn test_try_extend_dropped_max_keys() {
+		let ids = (0..100).map(|i| format!("k{i}")).collect::<Vec<_>>();
+		let mut body = testing::certs_body(ids.iter().enumerate().map(|(i, id)| {
+			(id.as_str(), if i < 8 { testing::RSA_A_CERT } else { "garbage" })
+		}));
+		let mut keys = Keys::<8>::new();
+		let dropped = keys.try_extend_dropped(crate::parse(&mut body)).unwrap();
+		assert_eq!(keys.len(), 8);
+		assert_eq!(dropped.len(), 92);
+		assert_eq!(*dropped[0], *b"k8");
+	}
+
+	#[test]
+	fn test_validate_bytes() {
+		let keys = testing::keys();
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &testing::claims(3600));
+		keys.validate_bytes::<serde_json::Value>(token.as_bytes()).unwrap();
+		let mut invalid = token.into_bytes();
+		invalid[0] = 0xff;
+		assert!(matches!(
+			keys.validate_bytes::<serde_json::Value>(&invalid),
+			Err(ValidateError::DecodeHeader(e)) if *e.kind() == ErrorKind::InvalidToken,
+		));
+	}
+
+	#[test]
+	fn test_bearer_token() {
+		for value in ["a.b.c", " a.b.c ", "Bearer a.b.c", "bearer  a.b.c", "BEARER\ta.b.c\r\n"] {
+			assert_eq!(bearer_token(value).unwrap(), "a.b.c", "{value:?}");
+		}
+		for value in ["Bearer", "Bearer ", "Bearer: a.b.c", "Bearera.b.c", "Bearer a.b.c d"] {
+			assert!(matches!(bearer_token(value), Err(ValidateError::MalformedAuthorization)), "{value:?}");
+		}
+
+		let keys = testing::keys();
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &testing::claims(3600));
+		keys.validate_authorization_header::<serde_json::Value>(&format!("Bearer {token}")).unwrap();
+	}
+
+	#[test]
+	fn test_into_iter() {
+		let keys = testing::keys();
+		let mut ids = Vec::new();
+		for (id, _) in &keys { ids.push(id); }
+		assert_eq!(ids, [hash(b"a"), hash(b"b")]);
+		assert_eq!((&keys).into_iter().len(), 2);
+	}
+
+	#[test]
+	fn test_iter_sorted() {
+		let mut body = testing::certs_body([("c", testing::RSA_A_CERT), ("a", testing::RSA_A_CERT), ("b", testing::RSA_B_CERT)]);
+		let keys = Keys::<3>::from_certs_json(&mut body).unwrap();
+		assert_eq!(keys.iter_sorted().map(|(id, _)| id).collect::<Vec<_>>(), [b"a", b"b", b"c"]);
+	}
+
+	#[test]
+	fn test_try_extend_dropped() {
+		let mut body = testing::certs_body([("a", testing::RSA_A_CERT), ("b", "garbage"), ("c", "garbage")]);
+		let mut keys = Keys::<1>::new();
+		let dropped = keys.try_extend_dropped(crate::parse(&mut body)).unwrap();
+		assert_eq!(dropped, [Box::from(&b"b"[..]), Box::from(&b"c"[..])]);
+		assert!(keys.contains(b"a"));
+		assert_eq!((keys.len(), keys.capacity()), (1, 1));
+
+		let mut body = testing::certs_body([("a", testing::RSA_A_CERT)]);
+		assert!(Keys::<1>::new().try_extend_dropped(crate::parse(&mut body)).unwrap().is_empty());
+	}
+
+	#[test]
+	fn test_validate_unknown_key_fast() {
+		use base64::Engine;
+		let keys = testing::keys();
+		assert!(keys.contains(b"a"));
+		assert!(!keys.contains(b"z"));
+		let header = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(br#"{"alg":"RS256","kid":"z"}"#);
+		// The payload and signature are garbage, so reaching a full decode would yield a `DecodeToken`.
+		let token = format!("{header}.not-a-payload.not-a-signature");
+		assert!(matches!(keys.validate::<serde_json::Value>(&token), Err(ValidateError::UnknownKey)));
+	}
+
+	#[test]
+	fn test_fast_kid() {
+		use base64::Engine;
+		let kid = |header: &str| {
+			let token = format!("{}.payload.signature", base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(header));
+			let mut buffer = [0u8; 192];
+			fast_kid(&token, &mut buffer).map(<[u8]>::to_vec)
+		};
+		assert_eq!(kid(r#"{"alg":"RS256","kid":"abc","typ":"JWT"}"#).as_deref(), Some(&b"abc"[..]));
+		assert_eq!(kid(r#"{ "kid" : "abc" }"#).as_deref(), Some(&b"abc"[..]));
+		for ambiguous in [
+			r#"{"alg":"RS256"}"#,
+			r#"{"kid":"a\"b"}"#,
+			r#"{"kid":"a","kid":"b"}"#,
+			r#"{"jwk":{"kid":"a"}}"#,
+			r#"{"x5c":["kid"],"kid":"a"}"#,
+			r#"{"typ":"kid"}"#,
+			r#"{"kid":1}"#,
+		] {
+			assert_eq!(kid(ambiguous), None, "{ambiguous}");
+		}
+		assert_eq!(kid(&format!(r#"{{"kid":"{}"}}"#, "a".repeat(200))), None);
+
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &testing::claims(3600));
+		assert_eq!(with_kid(&token, <[u8]>::to_vec).unwrap(), b"a");
+		let escaped = testing::sign(Some("a\"b"), testing::RSA_A_KEY, &testing::claims(3600));
+		assert_eq!(with_kid(&escaped, <[u8]>::to_vec).unwrap(), b"a\"b");
+	}
+
+	#[test]
+	fn test_validate_auth_age() {
+		use std::time::Duration;
+		let keys = testing::keys();
+		let mut claims = testing::claims(3600);
+		let with_auth_time = |claims: &mut serde_json::Value, ago: u64| {
+			claims["auth_time"] = (testing::now() - ago).into();
+			testing::sign(Some("a"), testing::RSA_A_KEY, claims)
+		};
+		let fresh = with_auth_time(&mut claims, 30);
+		keys.validate_auth_age::<serde_json::Value>(&fresh, Duration::from_secs(300)).unwrap();
+		let stale = with_auth_time(&mut claims, 600);
+		assert!(matches!(keys.validate_auth_age::<serde_json::Value>(&stale, Duration::from_secs(300)), Err(ValidateError::AuthTooOld)));
+		let missing = testing::sign(Some("a"), testing::RSA_A_KEY, &testing::claims(3600));
+		assert!(matches!(keys.validate_auth_age::<serde_json::Value>(&missing, Duration::from_secs(300)), Err(ValidateError::MissingAuthTime)));
+	}
+
+	#[test]
+	fn test_validate_decision() {
+		let keys = testing::keys();
+		let claims = testing::claims(3600);
+		let decision = keys.validate_decision(&testing::sign(Some("a"), testing::RSA_A_KEY, &claims));
+		assert!(decision.valid);
+		assert!(decision.reason.is_none());
+		assert_eq!(decision.sub.as_deref(), Some("1234567890"));
+		assert_eq!(decision.email.as_deref(), Some("user@example.com"));
+		assert_eq!(decision.exp, claims["exp"].as_u64());
+
+		let decision = keys.validate_decision(&testing::sign(Some("a"), testing::RSA_A_KEY, &testing::claims(-3600)));
+		assert!(!decision.valid);
+		assert!(matches!(decision.reason, Some(ValidateError::DecodeToken(e)) if *e.kind() == ErrorKind::ExpiredSignature));
+		assert_eq!((decision.sub, decision.email, decision.exp), (None, None, None));
+	}
+
+	#[test]
+	fn test_validate_with_algorithms() {
+		use jsonwebtoken::Algorithm;
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &testing::claims(3600));
+		let keys = testing::keys();
+		keys.validate_with_algorithms::<serde_json::Value>(&token, &[Algorithm::RS256]).unwrap();
+		assert!(matches!(
+			keys.validate_with_algorithms::<serde_json::Value>(&token, &[Algorithm::ES256]),
+			Err(ValidateError::DecodeToken(e)) if *e.kind() == ErrorKind::InvalidAlgorithm,
+		));
+	}
+
+	#[test]
+	fn test_validate_with_issuer_fn() {
+		let is_firebase = |iss: &str| iss.strip_prefix("https://securetoken.google.com/").is_some_and(|project| !project.is_empty());
+		let keys = testing::keys();
+
+		let mut claims = testing::claims(3600);
+		claims["iss"] = "https://securetoken.google.com/my-project".into();
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &claims);
+		keys.validate_with_issuer_fn::<serde_json::Value>(&token, is_firebase).unwrap();
+		assert!(keys.validate::<serde_json::Value>(&token).is_err());
+
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &testing::claims(3600));
+		assert!(matches!(
+			keys.validate_with_issuer_fn::<serde_json::Value>(&token, is_firebase),
+			Err(ValidateError::InvalidIssuer),
+		));
+	}
+
+	#[test]
+	fn test_validate_workspace() {
+		let keys = testing::keys();
+		let mut claims = testing::claims(3600);
+		claims["hd"] = "example.com".into();
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &claims);
+		assert_eq!(keys.validate_workspace(&token).unwrap(), WorkspaceUser {
+			sub: "1234567890".to_owned(),
+			email: "user@example.com".to_owned(),
+			email_verified: true,
+			hd: "example.com".to_owned(),
+		});
+
+		claims["email_verified"] = false.into();
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &claims);
+		assert!(matches!(keys.validate_workspace(&token), Err(ValidateError::EmailNotVerified)));
+
+		let consumer = testing::sign(Some("a"), testing::RSA_A_KEY, &testing::claims(3600));
+		assert!(matches!(keys.validate_workspace(&consumer), Err(ValidateError::MissingHostedDomain)));
+	}
+
+	#[test]
+	fn test_validate_aud() {
+		let keys = testing::keys();
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &testing::claims(3600));
+		keys.validate_aud::<serde_json::Value>(&token, &["client.apps.googleusercontent.com"]).unwrap();
+		assert!(matches!(
+			keys.validate_aud::<serde_json::Value>(&token, &["other.apps.googleusercontent.com"]),
+			Err(ValidateError::Audience),
+		));
+		let clients = ["web.apps.googleusercontent.com", "client.apps.googleusercontent.com", "android.apps.googleusercontent.com"];
+		keys.validate_aud::<serde_json::Value>(&token, &clients).unwrap();
+
+		let mut claims = testing::claims(3600);
+		claims.as_object_mut().unwrap().remove("aud");
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &claims);
+		assert!(matches!(
+			keys.validate_aud::<serde_json::Value>(&token, &["client.apps.googleusercontent.com"]),
+			Err(ValidateError::Audience),
+		));
+		let mut claims = testing::claims(3600);
+		claims.as_object_mut().unwrap().remove("exp");
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &claims);
+		assert!(matches!(
+			keys.validate_aud::<serde_json::Value>(&token, &["client.apps.googleusercontent.com"]),
+			Err(ValidateError::DecodeToken(e)) if matches!(e.kind(), ErrorKind::MissingRequiredClaim(claim) if claim == "exp"),
+		));
+	}
+
+	#[test]
+	fn test_key_kinds() {
+		let mut keys = testing::keys();
+		assert!(keys.push(b"ec", testing::EC_CERT.as_bytes()).unwrap());
+		assert_eq!(keys.get_kind(b"a").unwrap().1, KeyKind::Rsa);
+		assert_eq!(keys.get_kind(b"ec").unwrap().1, KeyKind::Ec);
+		assert!(keys.push(b"bad", b"not a PEM").is_err());
+		assert_eq!(decode_pem(testing::RSA_A_CERT.as_bytes()).unwrap().1, KeyKind::Rsa);
+		assert_eq!(decode_pem(testing::EC_CERT.as_bytes()).unwrap().1, KeyKind::Ec);
+		assert!(decode_pem(b"not a PEM").is_err());
+
+		let token = testing::sign_ec(Some("ec"), testing::EC_KEY, &testing::claims(3600));
+		keys.validate::<serde_json::Value>(&token).unwrap();
+		keys.snapshot().validate::<serde_json::Value>(&token).unwrap();
+		keys.validate_with_algorithms::<serde_json::Value>(&token, &[Algorithm::RS256, Algorithm::ES256]).unwrap();
+
+		// A key validates only its kind's algorithms, whatever the token claims.
+		let token = testing::sign(Some("ec"), testing::RSA_A_KEY, &testing::claims(3600));
+		assert!(matches!(keys.validate::<serde_json::Value>(&token), Err(ValidateError::AlgorithmMismatch)));
+		assert!(matches!(keys.snapshot().validate::<serde_json::Value>(&token), Err(ValidateError::AlgorithmMismatch)));
+		assert!(matches!(
+			keys.validate_with_algorithms::<serde_json::Value>(&token, &[Algorithm::RS256, Algorithm::ES256]),
+			Err(ValidateError::AlgorithmMismatch),
+		));
+		let token = testing::sign_ec(Some("a"), testing::EC_KEY, &testing::claims(3600));
+		assert!(matches!(keys.validate::<serde_json::Value>(&token), Err(ValidateError::AlgorithmMismatch)));
+	}
+
+	#[test]
+	fn test_clone_debug() {
+		let keys = testing::keys();
+		let clone = keys.clone();
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &testing::claims(3600));
+		drop(keys);
+		clone.validate::<serde_json::Value>(&token).unwrap();
+		assert_eq!(format!("{clone:?}"), r#"Keys { len: 2, keys: [("a", Rsa), ("b", Rsa)] }"#);
+	}
+
+	#[test]
+	fn test_merge() {
+		let mut keys = Keys::<3>::new();
+		keys.push(b"a", testing::RSA_A_CERT.as_bytes()).unwrap();
+		keys.push(b"b", testing::RSA_B_CERT.as_bytes()).unwrap();
+		let incoming = [(&b"b"[..], testing::RSA_A_CERT.as_bytes()), (b"c", testing::EC_CERT.as_bytes())];
+		assert!(keys.merge(incoming).unwrap());
+		assert_eq!(keys.key_ids().collect::<Vec<_>>(), ["a", "b", "c"]);
+		let token = testing::sign(Some("b"), testing::RSA_A_KEY, &testing::claims(3600));
+		keys.validate::<serde_json::Value>(&token).unwrap();
+		assert_eq!(keys.get_kind(b"c").unwrap().1, KeyKind::Ec);
+		assert!(!keys.merge([(&b"d"[..], testing::RSA_B_CERT.as_bytes())]).unwrap());
+		assert_eq!(keys.len(), 3);
+	}
+
+	#[test]
+	fn test_from_env() {
+		let body = testing::certs_body([("a", testing::RSA_A_CERT), ("b", testing::RSA_B_CERT)]);
+		std::env::set_var("GOOGLE_PEM_TEST_FROM_ENV", String::from_utf8(body).unwrap());
+		let keys = Keys::<2>::from_env("GOOGLE_PEM_TEST_FROM_ENV").unwrap();
+		assert!(keys.contains(b"a") && keys.contains(b"b"));
+		assert!(matches!(Keys::<1>::from_env("GOOGLE_PEM_TEST_FROM_ENV"), Err(FromEnvError::Capacity)));
+		assert!(matches!(Keys::<2>::from_env("GOOGLE_PEM_TEST_FROM_ENV_UNSET"), Err(FromEnvError::Var(_))));
+	}
+
+	#[test]
+	fn test_from_certs_json() {
+		let body = testing::certs_body([("a", testing::RSA_A_CERT), ("b", testing::RSA_B_CERT)]);
+		let keys = Keys::<2>::from_certs_json(&mut body.clone()).unwrap();
+		assert!(keys.contains(b"a") && keys.contains(b"b"));
+		assert!(matches!(Keys::<1>::from_certs_json(&mut body.clone()), Err(FromCertsJsonError::Capacity)));
+		let mut invalid = testing::certs_body([("a", "not a pem")]);
+		assert!(matches!(Keys::<2>::from_certs_json(&mut invalid), Err(FromCertsJsonError::Jwt(_))));
+	}
+
+	#[test]
+	fn test_key_ids() {
+		let mut keys = testing::keys();
+		keys.push(b"ec", testing::EC_CERT.as_bytes()).unwrap();
+		assert_eq!(keys.key_ids().collect::<Vec<_>>(), ["a", "b", "ec"]);
+	}
+
+	#[test]
+	fn test_get_hash_collision() {
+		let mut keys = testing::keys();
+		// Stub a collision: make "a" hash like "b", so "a" is found first by hash when looking up "b".
+		keys.id[0] = MaybeUninit::new(hash(b"b"));
+		let b = keys.get(b"b").unwrap() as *const DecodingKey;
+		assert!(std::ptr::eq(b, unsafe { &keys.key[1].assume_init_ref().key }));
+		let snapshot = keys.snapshot();
+		assert!(Arc::ptr_eq(snapshot.get(b"b").unwrap(), &snapshot.keys[1].2));
+	}
+
+	#[test]
+	fn test_to_jwks_json() {
+		let mut keys = testing::keys();
+		keys.push(b"ec", testing::EC_CERT.as_bytes()).unwrap();
+		keys.push_key(b"opaque", DecodingKey::from_rsa_components(testing::RSA_A_N, testing::RSA_A_E).unwrap());
+		let jwks = serde_json::from_str::<jsonwebtoken::jwk::JwkSet>(&keys.to_jwks_json()).unwrap();
+		assert_eq!(jwks.keys.len(), 3);
+		assert!(jwks.find("opaque").is_none());
+
+		let mut parsed = Keys::<3>::new();
+		for jwk in &jwks.keys {
+			let kind = match jwk.algorithm { jsonwebtoken::jwk::AlgorithmParameters::EllipticCurve(_) => KeyKind::Ec, _ => KeyKind::Rsa };
+			parsed.push_key_of_kind(jwk.common.key_id.as_ref().unwrap().as_bytes(), DecodingKey::from_jwk(jwk).unwrap(), kind);
+		}
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &testing::claims(3600));
+		parsed.validate::<serde_json::Value>(&token).unwrap();
+		let token = testing::sign_ec(Some("ec"), testing::EC_KEY, &testing::claims(3600));
+		parsed.validate::<serde_json::Value>(&token).unwrap();
+	}
+
+	#[test]
+	fn test_validate_with_leeway() {
+		let keys = testing::keys();
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &testing::claims(-30));
+		keys.validate_with_leeway::<serde_json::Value>(&token, 60).unwrap();
+		assert!(matches!(
+			keys.validate_with_leeway::<serde_json::Value>(&token, 10),
+			Err(ValidateError::DecodeToken(e)) if *e.kind() == ErrorKind::ExpiredSignature,
+		));
+	}
+
+	#[test]
+	fn test_validation_builder() {
+		assert_eq!(ValidationBuilder::new().build(), *VALIDATION);
+
+		let keys = testing::keys();
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &testing::claims(-30));
+		let builder = ValidationBuilder::new()
+			.issuers(&["https://accounts.google.com"])
+			.audiences(&["client.apps.googleusercontent.com"])
+			.leeway(60)
+			.algorithms(&[Algorithm::RS256]);
+		keys.validate_with_builder::<serde_json::Value>(&token, &builder).unwrap();
+		for builder in [
+			builder.clone().issuers(&["other"]),
+			builder.clone().audiences(&["other"]),
+			builder.clone().leeway(0),
+			builder.clone().algorithms(&[Algorithm::RS384]),
+		] {
+			assert!(keys.validate_with_builder::<serde_json::Value>(&token, &builder).is_err());
+		}
+	}
+
+	#[test]
+	fn test_validation_builder_not_yet_valid() {
+		let keys = testing::keys();
+		let mut claims = testing::claims(3600);
+		claims["nbf"] = (testing::now() + 300).into();
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &claims);
+		keys.validate_with_builder::<serde_json::Value>(&token, &ValidationBuilder::new()).unwrap();
+		assert!(matches!(
+			keys.validate_with_builder::<serde_json::Value>(&token, &ValidationBuilder::new().not_before(true)),
+			Err(ValidateError::NotYetValid),
+		));
+
+		let mut claims = testing::claims(3600);
+		claims["iat"] = (testing::now() + 300).into();
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &claims);
+		keys.validate_with_builder::<serde_json::Value>(&token, &ValidationBuilder::new()).unwrap();
+		assert!(matches!(
+			keys.validate_with_builder::<serde_json::Value>(&token, &ValidationBuilder::new().reject_future_iat(true)),
+			Err(ValidateError::NotYetValid),
+		));
+		keys.validate_with_builder::<serde_json::Value>(&token, &ValidationBuilder::new().reject_future_iat(true).leeway(600)).unwrap();
+	}
+
+	#[test]
+	fn test_validate_full() {
+		#[derive(Debug, serde::Deserialize)]
+		struct Email { email: String }
+		let claims = testing::claims(3600);
+		let token = testing::sign(Some("b"), testing::RSA_A_KEY, &claims);
+		assert!(testing::keys().validate_full::<Email>(&token).is_err());
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &claims);
+		let token = testing::keys().validate_full::<Email>(&token).unwrap();
+		assert_eq!(token.claims.email, "user@example.com");
+		assert_eq!(token.registered, RegisteredClaims {
+			iss: "https://accounts.google.com".to_owned(),
+			sub: Some("1234567890".to_owned()),
+			exp: claims["exp"].as_u64().unwrap(),
+			iat: claims["iat"].as_u64(),
+			nbf: None,
+			aud: vec!["client.apps.googleusercontent.com".to_owned()],
+		});
+		assert_eq!(token.kid, "a");
+		assert_eq!(token.alg, Algorithm::RS256);
+
+		let mut claims = testing::claims(3600);
+		claims["aud"] = serde_json::json!(["x", "y"]);
+		claims["nbf"] = claims["iat"].clone();
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &claims);
+		let registered = testing::keys().validate_full::<serde::de::IgnoredAny>(&token).unwrap().registered;
+		assert_eq!((registered.aud, registered.nbf), (vec!["x".to_owned(), "y".to_owned()], claims["iat"].as_u64()));
+	}
+
+	#[test]
+	fn test_validate_from_body() {
+		let mut body = testing::certs_body([("a", testing::RSA_A_CERT)]);
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &testing::claims(3600));
+		validate_from_body::<serde_json::Value>(&mut body.clone(), &token).unwrap();
+		let token = testing::sign(Some("b"), testing::RSA_A_KEY, &testing::claims(3600));
+		assert!(matches!(
+			validate_from_body::<serde_json::Value>(&mut body, &token),
+			Err(ValidateFromBodyError::Validate(ValidateError::UnknownKey)),
+		));
+		let mut body = testing::certs_body([("a", "garbage")]);
+		assert!(matches!(
+			validate_from_body::<serde_json::Value>(&mut body, &token),
+			Err(ValidateFromBodyError::Keys(FromCertsJsonError::Jwt(_))),
+		));
+	}
+
+	#[test]
+	fn test_validate_with() {
+		let keys = testing::keys();
+		let mut claims = testing::claims(3600);
+		claims["iss"] = "https://securetoken.google.com/my-project".into();
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &claims);
+
+		let mut validation = jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::RS256);
+		validation.set_issuer(&["https://securetoken.google.com/my-project"]);
+		validation.set_audience(&["client.apps.googleusercontent.com"]);
+		keys.validate_with::<serde_json::Value>(&token, &validation).unwrap();
+		assert!(keys.validate::<serde_json::Value>(&token).is_err());
+
+		validation.set_audience(&["other.apps.googleusercontent.com"]);
+		assert!(matches!(
+			keys.validate_with::<serde_json::Value>(&token, &validation),
+			Err(ValidateError::DecodeToken(e)) if *e.kind() == ErrorKind::InvalidAudience,
+		));
+	}
+
+	#[test]
+	fn test_validate_any_key() {
+		// The matching key is tried last.
+		let mut keys = Keys::with_default_capacity();
+		keys.push(b"b", testing::RSA_B_CERT.as_bytes()).unwrap();
+		keys.push(b"a", testing::RSA_A_CERT.as_bytes()).unwrap();
+		for kid in [None, Some("unknown")] {
+			let token = testing::sign(kid, testing::RSA_A_KEY, &testing::claims(3600));
+			assert!(keys.validate::<serde_json::Value>(&token).is_err());
+			keys.validate_any_key::<serde_json::Value>(&token).unwrap();
+		}
+		assert!(matches!(
+			Keys::with_default_capacity().validate_any_key::<serde_json::Value>(&testing::sign(None, testing::RSA_A_KEY, &testing::claims(3600))),
+			Err(ValidateError::UnknownKey),
+		));
+	}
+
+	#[test]
+	fn test_validate_any_key_all_failed() {
+		let token = testing::sign(None, testing::RSA_A_KEY, &testing::claims(-3600));
+		match testing::keys().validate_any_key::<serde_json::Value>(&token) {
+			Err(ValidateError::AllKeysFailed(errors)) => {
+				let kinds = errors.iter().map(|(id, e)| (*id, e.kind().clone())).collect::<Vec<_>>();
+				assert_eq!(kinds, [
+					(hash(b"a"), ErrorKind::ExpiredSignature),
+					(hash(b"b"), ErrorKind::InvalidSignature),
+				]);
+			},
+			other => panic!("expected all keys to fail, got {other:?}"),
+		}
+	}
+
+	#[test]
+	fn test_snapshot_validate() {
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &testing::claims(3600));
+		let snapshot = testing::keys().snapshot();
+		assert_eq!(snapshot.len(), 2);
+		snapshot.validate::<serde_json::Value>(&token).unwrap();
+		assert!(matches!(
+			KeysSnapshot::default().validate::<serde_json::Value>(&token),
+			Err(ValidateError::UnknownKey),
+		));
+	}
+
+	#[test]
+	fn test_validate_with_clock_offset() {
+		// Expired beyond the default 60 seconds leeway, by the local clock.
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &testing::claims(-120));
+		let keys = testing::keys();
+		assert!(matches!(
+			keys.validate::<serde_json::Value>(&token),
+			Err(ValidateError::DecodeToken(e)) if *e.kind() == ErrorKind::ExpiredSignature,
+		));
+		assert!(matches!(
+			keys.validate_with_clock_offset::<serde_json::Value>(&token, 0),
+			Err(ValidateError::DecodeToken(e)) if *e.kind() == ErrorKind::ExpiredSignature,
+		));
+		// A node whose clock runs two minutes ahead.
+		keys.validate_with_clock_offset::<serde_json::Value>(&token, -120).unwrap();
+	}
+
+	#[test]
+	fn test_validate_at() {
+		const ISSUED: u64 = 1_700_000_000;
+		let mut claims = testing::claims(0);
+		claims["iat"] = ISSUED.into();
+		claims["exp"] = (ISSUED + 3600).into();
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &claims);
+		let keys = testing::keys();
+		let at = |secs| std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs);
+		keys.validate_at::<serde_json::Value>(&token, at(ISSUED)).unwrap();
+		assert!(matches!(
+			keys.validate_at::<serde_json::Value>(&token, at(ISSUED + 3600 + 61)),
+			Err(ValidateError::DecodeToken(e)) if *e.kind() == ErrorKind::ExpiredSignature,
+		));
+		assert!(keys.validate::<serde_json::Value>(&token).is_err());
+	}
+
+	#[test]
+	fn test_validate_claim() {
+		let keys = testing::keys();
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &testing::claims(3600));
+		assert_eq!(keys.validate_claim(&token, "email").unwrap(), Some("user@example.com".into()));
+		assert_eq!(keys.validate_claim(&token, "email_verified").unwrap(), Some(true.into()));
+		assert_eq!(keys.validate_claim(&token, "name").unwrap(), None);
+		let expired = testing::sign(Some("a"), testing::RSA_A_KEY, &testing::claims(-3600));
+		assert!(matches!(
+			keys.validate_claim(&expired, "email"),
+			Err(ValidateError::DecodeToken(e)) if *e.kind() == ErrorKind::ExpiredSignature,
+		));
+		assert_eq!(keys.validate_claim(&token, "exp").unwrap(), Some((testing::now() + 3600).into()));
+
+		let mut claims = testing::claims(3600);
+		claims["iss"] = "https://example.com".into();
+		let foreign = testing::sign(Some("a"), testing::RSA_A_KEY, &claims);
+		assert!(matches!(
+			keys.validate_claim(&foreign, "email"),
+			Err(ValidateError::DecodeToken(e)) if *e.kind() == ErrorKind::InvalidIssuer,
+		));
+		let forged = testing::sign(Some("b"), testing::RSA_A_KEY, &testing::claims(3600));
+		assert!(matches!(
+			keys.validate_claim(&forged, "email"),
+			Err(ValidateError::DecodeToken(e)) if *e.kind() == ErrorKind::InvalidSignature,
+		));
+		let mismatched = testing::sign_ec(Some("a"), testing::EC_KEY, &testing::claims(3600));
+		assert!(matches!(keys.validate_claim(&mismatched, "email"), Err(ValidateError::AlgorithmMismatch)));
+		let mut claims = testing::claims(3600);
+		claims.as_object_mut().unwrap().remove("exp");
+		let unexpiring = testing::sign(Some("a"), testing::RSA_A_KEY, &claims);
+		assert!(matches!(
+			keys.validate_claim(&unexpiring, "email"),
+			Err(ValidateError::DecodeToken(e)) if matches!(e.kind(), ErrorKind::MissingRequiredClaim(claim) if claim == "exp"),
+		));
+	}
+
+	#[test]
+	fn test_verify_at_hash() {
+		let mut claims = testing::claims(3600);
+		claims["at_hash"] = "mLzc-GFcHSVfaiaPxAa37w".into();
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &claims);
+		let keys = testing::keys();
+		keys.verify_at_hash::<serde_json::Value>(&token, "ya29.access-token").unwrap();
+		assert!(matches!(
+			keys.verify_at_hash::<serde_json::Value>(&token, "ya29.other-token"),
+			Err(ValidateError::AtHashMismatch),
+		));
+	}
+
+	#[test]
+	fn test_peek() {
+		let token = testing::sign(Some("unknown"), testing::RSA_A_KEY, &testing::claims(-3600));
+		let header = Keys::<KEYS_CAPACITY>::peek_header(&token).unwrap();
+		assert_eq!((header.kid.as_deref(), header.alg), (Some("unknown"), Algorithm::RS256));
+		let claims = Keys::<KEYS_CAPACITY>::peek_claims_unverified::<serde_json::Value>(&token).unwrap();
+		assert_eq!(claims["iss"], "https://accounts.google.com");
+		assert!(matches!(Keys::<KEYS_CAPACITY>::peek_header("garbage"), Err(ValidateError::DecodeHeader(_))));
+	}
+
+	#[test]
+	fn test_verify_c_hash() {
+		let mut claims = testing::claims(3600);
+		claims["c_hash"] = "OV4Mo1zSL3jxuSyJTiYOyQ".into();
+		let token = testing::sign(Some("a"), testing::RSA_A_KEY, &claims);
+		let keys = testing::keys();
+		keys.verify_c_hash::<serde_json::Value>(&token, "4/code").unwrap();
+		assert!(matches!(
+			keys.verify_c_hash::<serde_json::Value>(&token, "4/other"),
+			Err(ValidateError::CHashMismatch),
+		));
+	}
+}
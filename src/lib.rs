@@ -4,17 +4,62 @@
 //! - Fetch, cache, and validate keys: [`cache::Keys`].
 //! - Fetch and validate keys: [`keys::Keys`].
 //! - Fetch keys: [`fetch::into`], [`fetch::process_headers`] (or [`fetch::body`]), and [`Parse`].
+//!
+//! Everything that touches the network is behind the (default) `net` feature. Without it, only
+//! parsing, HTTP processing, and validating against keys you obtained yourself are available.
+//! The `cache` feature (which `net` enables) has [`cache::Keys`] without the TCP/TLS stack, to
+//! refresh from a [`fetch::KeyFetcher`] of your own, e.g. over a browser's `fetch()` on wasm32.
+//!
+//! The `reqwest` feature fetches with a [`reqwest`](https://docs.rs/reqwest) client instead,
+//! which needs a TLS backend enabled on your reqwest dependency (e.g. its `rustls-tls` feature).
+//!
+//! With the `tracing` feature, fetching and validating emit [`tracing`](https://docs.rs/tracing)
+//! spans and events.
+//!
+//! Tokens are validated with [`jsonwebtoken`], which is re-exported (along with its most common
+//! types) so that they can be named without depending on a possibly mismatched version of it.
 
 pub mod fetch;
 mod parse;
-pub use parse::Parse;
+pub use parse::{Parse, TryParse, ParseJwk, parse_owned, ParseError};
+#[cfg(fuzzing)]
+#[doc(hidden)]
+pub use parse::unescape;
 pub mod keys;
+#[cfg(feature = "cache")]
 pub mod cache;
+#[cfg(feature = "net")]
+pub mod discovery;
 
+#[cfg(feature = "cache")]
 pub use cache::Keys;
 
+pub use jsonwebtoken;
+pub use jsonwebtoken::{Algorithm, DecodingKey, TokenData, Validation};
+
+#[cfg(test)]
+mod testing;
+
 /// Parses PEM data into a (key id, escaped key) iterator.
-pub fn parse<'a>(data: &'a mut [u8]) -> impl Iterator<Item = (&'a [u8], &'a [u8])> {
+///
+/// See [`parse_owned`] for data you can't (or don't want to) mutate, [`parse_checked`] to catch a
+/// mis-sliced buffer, and [`try_parse`] to tell a malformed body from an empty one.
+pub fn parse(data: &mut [u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
 	parse::Parse::new(data)
 }
 
+/// Parses PEM data into a (key id, escaped key) iterator, failing right away if the data doesn't
+/// look like a PEM endpoint body (e.g. it still has the HTTP headers).
+///
+/// See [`Parse::checked`].
+pub fn parse_checked(data: &mut [u8]) -> Result<impl Iterator<Item = (&[u8], &[u8])>, ParseError> {
+	parse::Parse::checked(data)
+}
+
+/// Parses PEM data into a (key id, escaped key) iterator which fails on a malformed body.
+///
+/// See [`TryParse`].
+pub fn try_parse(data: &mut [u8]) -> impl Iterator<Item = Result<(&[u8], &[u8]), ParseError>> {
+	parse::TryParse::new(data)
+}
+
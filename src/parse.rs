@@ -1,27 +1,35 @@
 //! PEM endpoint parsing.
 
-use std::marker::PhantomData;
+use std::borrow::Cow;
+
+use jsonwebtoken::DecodingKey;
 
 /// A parsing iterator for the PEM endpoint.
 ///
 /// It parses the HTTP body of the PEM endpoint, and yields tuples of key ID and key.
 ///
-/// Note: this iterator modifies its source in-place (to turn JSON "\n" sequence into actual
-/// newline characters).
+/// Note: this iterator modifies its source in-place (to turn JSON escape sequences, e.g. "\n",
+/// into the actual characters).
 pub struct Parse<'a> {
-	ptr: *mut u8,
-	len: usize,
-	phantom: PhantomData<&'a ()>,
+	/// The data left to parse.
+	data: &'a mut [u8],
 }
 
 impl<'a> Parse<'a> {
 	/// Creates a new [`Parse`]r.
-	#[inline] pub fn new(data: &'a mut [u8]) -> Self {
-		Self {
-			ptr: data.as_mut_ptr(),
-			len: data.len(),
-			phantom: PhantomData::default(),
-		}
+	#[inline] pub fn new(data: &'a mut [u8]) -> Self { Self { data } }
+
+	/// Creates a new [`Parse`]r, checking first that the data looks like a PEM endpoint body: a
+	/// JSON object that's either empty or starts with a key.
+	///
+	/// This catches mis-sliced buffers, e.g. ones that still have the HTTP headers; see [`TryParse`]
+	/// to check the whole body's framing.
+	pub fn checked(data: &'a mut [u8]) -> Result<Self, ParseError> {
+		let object = data.trim_ascii();
+		let entries = object.strip_prefix(b"{").and_then(|object| object.strip_suffix(b"}")).ok_or(ParseError::Malformed)?;
+		let entries = entries.trim_ascii();
+		if !entries.is_empty() && !entries.starts_with(b"\"") { return Err(ParseError::Malformed); }
+		Ok(Self::new(data))
 	}
 }
 
@@ -34,52 +42,402 @@ impl<'a> Iterator for Parse<'a> {
 		let mut indices = [0;4];
 		let mut index_pos = 0;
 
-		for i in 0..self.len {
-			if unsafe { *self.ptr.add(i) } == b'"' {
-				indices[index_pos] = i;
-				index_pos += 1;
-				if index_pos == 4 {
-					let id = unsafe {
-						std::slice::from_raw_parts(self.ptr.add(indices[0] + 1), indices[1] - indices[0] - 1)
-					};
-					let key = unsafe {
-						std::slice::from_raw_parts_mut(self.ptr.add(indices[2] + 1), indices[3] - indices[2] - 1)
-					};
-					let key = &*unescape(key);
-					self.ptr = unsafe { self.ptr.add(i + 1) };
-					return Some((id, key));
+		let mut i = 0;
+		while i < self.data.len() {
+			match self.data[i] {
+				b'\\' => i += 1, // an escaped character, which can't end the string
+				b'"' => {
+					indices[index_pos] = i;
+					index_pos += 1;
+					if index_pos == 4 {
+						let (entry, rest) = std::mem::take(&mut self.data).split_at_mut(i + 1);
+						self.data = rest;
+						let (id, key) = entry.split_at_mut(indices[2]);
+						let id = &mut id[indices[0] + 1..indices[1]];
+						let key = &mut key[1..indices[3] - indices[2]];
+						return Some((unescape(id), unescape(key)));
+					}
 				}
+				_ => {}
 			}
+			i += 1;
 		}
-		self.len = 0;
+		self.data = &mut [];
 		None
 	}
 }
 
-/// Changes "\\n" into "\n".
+/// A fallible [`Parse`], which tells a malformed body from the end of a well-formed one.
+///
+/// It only checks the body's framing (the braces and the entries being complete), not that it's
+/// valid JSON; see [`parse_owned`] for that.
+pub struct TryParse<'a> {
+	parse: Parse<'a>,
+	state: TryParseState,
+}
+
+/// The state of a [`TryParse`].
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TryParseState { Start, Entries, Done }
+
+impl<'a> TryParse<'a> {
+	/// Creates a new [`TryParse`]r.
+	#[inline] pub fn new(data: &'a mut [u8]) -> Self { Self { parse: Parse::new(data), state: TryParseState::Start } }
+
+	/// Skips the data up to the next entry, failing if it's malformed.
+	///
+	/// Returns whether there's a next entry.
+	fn skip_to_entry(&mut self) -> Result<bool, ParseError> {
+		let data = trim_start(std::mem::take(&mut self.parse.data));
+		let data = match (self.state, data.first().copied()) {
+			(TryParseState::Start, Some(b'{')) => trim_start(&mut data[1..]),
+			(TryParseState::Entries, Some(b',')) => {
+				let data = trim_start(&mut data[1..]);
+				if data.first() != Some(&b'"') { return Err(ParseError::Malformed); }
+				data
+			}
+			(TryParseState::Entries, Some(b'}')) => data,
+			_ => return Err(ParseError::Malformed),
+		};
+		match data.split_first() {
+			Some((b'"', _)) => { self.parse.data = data; Ok(true) }
+			Some((b'}', rest)) if rest.iter().all(u8::is_ascii_whitespace) => Ok(false),
+			_ => Err(ParseError::Malformed),
+		}
+	}
+}
+
+/// Skips leading whitespace.
+fn trim_start(data: &mut [u8]) -> &mut [u8] {
+	let start = data.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(data.len());
+	&mut data[start..]
+}
+
+impl<'a> Iterator for TryParse<'a> {
+	type Item = Result<(&'a [u8], &'a [u8]), ParseError>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		if self.state == TryParseState::Done { return None; }
+		let entry = self.skip_to_entry().and_then(|more| match more {
+			true => self.parse.next().ok_or(ParseError::Malformed).map(Some),
+			false => Ok(None),
+		});
+		self.state = match entry { Ok(Some(_)) => TryParseState::Entries, _ => TryParseState::Done };
+		entry.transpose()
+	}
+}
+
+/// Parses the HTTP body of the PEM endpoint into (key ID, key) pairs, in order.
+///
+/// Unlike [`Parse`], this leaves the data as is and fails on malformed data.
+pub fn parse_owned(data: &[u8]) -> Result<Vec<(String, Vec<u8>)>, ParseError> {
+	/// The entries of a JSON object of strings, in order.
+	struct Entries(Vec<(String, Vec<u8>)>);
+
+	impl<'de> serde::Deserialize<'de> for Entries {
+		fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+			struct Visitor;
+			impl<'de> serde::de::Visitor<'de> for Visitor {
+				type Value = Entries;
+				fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+					f.write_str("an object of key IDs to PEMs")
+				}
+				fn visit_map<A: serde::de::MapAccess<'de>>(self, mut map: A) -> Result<Entries, A::Error> {
+					let mut entries = Vec::with_capacity(map.size_hint().unwrap_or(2));
+					while let Some((id, key)) = map.next_entry::<String, String>()? {
+						entries.push((id, key.into_bytes()));
+					}
+					Ok(Entries(entries))
+				}
+			}
+			deserializer.deserialize_map(Visitor)
+		}
+	}
+
+	Ok(serde_json::from_slice::<Entries>(data)?.0)
+}
+
+/// [`parse_owned`] / [`TryParse`] error.
+#[derive(Debug, thiserror::Error)]
+pub enum ParseError {
+	#[error("malformed PEM endpoint body: {0}")]
+	Json(#[from] serde_json::Error),
+	#[error("malformed PEM endpoint body")]
+	Malformed,
+}
+
+/// A parsing iterator for the JWK endpoint.
+///
+/// It parses the HTTP body of the JWK endpoint (a [JWK Set](https://datatracker.ietf.org/doc/html/rfc7517#section-5)),
+/// and yields tuples of key ID and key, built from the RSA modulus and exponent.
+pub struct ParseJwk<'a> {
+	keys: std::vec::IntoIter<Jwk<'a>>,
+}
+
+/// A [JSON Web Key](https://datatracker.ietf.org/doc/html/rfc7517), as published by Google.
+#[derive(serde::Deserialize)]
+struct Jwk<'a> {
+	#[serde(borrow)]
+	kid: Cow<'a, str>,
+	#[serde(borrow)]
+	n: Cow<'a, str>,
+	#[serde(borrow)]
+	e: Cow<'a, str>,
+}
+
+impl<'a> ParseJwk<'a> {
+	/// Creates a new [`ParseJwk`]er.
+	///
+	/// Fails if the data isn't a JWK Set of RSA keys.
+	pub fn new(data: &'a [u8]) -> Result<Self, serde_json::Error> {
+		#[derive(serde::Deserialize)]
+		struct JwkSet<'a> {
+			#[serde(borrow)]
+			keys: Vec<Jwk<'a>>,
+		}
+		let set = serde_json::from_slice::<JwkSet>(data)?;
+		Ok(Self { keys: set.keys.into_iter() })
+	}
+}
+
+/// A [`ParseJwk`] item with the key's components.
+#[cfg(feature = "net")]
+type JwkWithComponents<'a> = (Cow<'a, str>, DecodingKey, Components);
+
+impl<'a> ParseJwk<'a> {
+	/// Like [`next`](Iterator::next), but with the key's components.
+	#[cfg(feature = "net")]
+	pub(crate) fn next_with_components(&mut self) -> Option<Result<JwkWithComponents<'a>, jsonwebtoken::errors::Error>> {
+		let jwk = self.keys.next()?;
+		Some(DecodingKey::from_rsa_components(&jwk.n, &jwk.e).map(|key| {
+			(jwk.kid, key, Components::Rsa { n: jwk.n.into_owned(), e: jwk.e.into_owned() })
+		}))
+	}
+}
+
+impl<'a> Iterator for ParseJwk<'a> {
+	type Item = Result<(Cow<'a, str>, DecodingKey), jsonwebtoken::errors::Error>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		let jwk = self.keys.next()?;
+		Some(DecodingKey::from_rsa_components(&jwk.n, &jwk.e).map(|key| (jwk.kid, key)))
+	}
+
+	fn size_hint(&self) -> (usize, Option<usize>) { self.keys.size_hint() }
+}
+
+/// The public components of a key, base64url-encoded as in a JWK.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Components {
+	/// An RSA key's modulus and exponent.
+	Rsa { n: String, e: String },
+	/// An EC key's curve and point.
+	Ec { crv: &'static str, x: String, y: String },
+}
+
+/// Extracts the public components of a PEM certificate or public key.
+pub(crate) fn pem_components(pem: &[u8]) -> Option<Components> {
+	use base64::Engine;
+
+	/// Reads a DER TLV, returning its tag, content, and the data after it.
+	fn tlv(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+		let (&tag, data) = data.split_first()?;
+		let (&len, mut data) = data.split_first()?;
+		let len = if len < 0x80 { len as usize } else {
+			let len_len = (len & 0x7f) as usize;
+			if len_len > std::mem::size_of::<usize>() || data.len() < len_len { return None; }
+			let (len, rest) = data.split_at(len_len);
+			data = rest;
+			len.iter().fold(0, |acc, &b| acc << 8 | b as usize)
+		};
+		if data.len() < len { return None; }
+		let (content, rest) = data.split_at(len);
+		Some((tag, content, rest))
+	}
+
+	/// Base64url-encodes an unsigned big-endian integer without leading zeros.
+	fn encode(int: &[u8]) -> String {
+		let leading_zeros = int.iter().take_while(|&&b| b == 0).count().min(int.len().saturating_sub(1));
+		base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(&int[leading_zeros..])
+	}
+
+	const RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+	const EC: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+
+	let pem = std::str::from_utf8(pem).ok()?;
+	let (label, rest) = pem.trim_start().strip_prefix("-----BEGIN ")?.split_once("-----")?;
+	let (base64, _) = rest.split_once("-----END ")?;
+	let base64 = base64.chars().filter(|c| !c.is_ascii_whitespace()).collect::<String>();
+	let der = base64::engine::general_purpose::STANDARD.decode(base64).ok()?;
+
+	let spki = match label {
+		"PUBLIC KEY" => tlv(&der)?.1,
+		"CERTIFICATE" => {
+			let (_, cert, _) = tlv(&der)?;
+			let (_, mut tbs, _) = tlv(cert)?;
+			if tbs.first() == Some(&0xa0) { tbs = tlv(tbs)?.2; } // version
+			for _ in 0..5 { tbs = tlv(tbs)?.2; } // serial number, signature, issuer, validity, subject
+			tlv(tbs)?.1
+		}
+		_ => return None,
+	};
+	let (_, algorithm, rest) = tlv(spki)?;
+	let (_, oid, _) = tlv(algorithm)?;
+	let (0x03, [0, key @ ..], _) = tlv(rest)? else { return None };
+	match oid {
+		RSA => {
+			let (_, key, _) = tlv(key)?;
+			let (_, n, rest) = tlv(key)?;
+			let (_, e, _) = tlv(rest)?;
+			Some(Components::Rsa { n: encode(n), e: encode(e) })
+		}
+		EC => {
+			let [0x04, point @ ..] = key else { return None };
+			let crv = match point.len() { 64 => "P-256", 96 => "P-384", _ => return None };
+			let (x, y) = point.split_at(point.len() / 2);
+			let encode = |int| base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(int);
+			Some(Components::Ec { crv, x: encode(x), y: encode(y) })
+		}
+		_ => None,
+	}
+}
+
+/// Changes JSON escape sequences (e.g. "\\n") into their characters, except for "\\u" ones.
 ///
 /// Returns the resulting (typically shorter) slice.
-fn unescape(s: &mut [u8]) -> &mut [u8] {
-	let mut n = 0;
-	for i in 0..s.len() {
-		if s[i] == b'\\' && (i + 1) < s.len() && s[i + 1] == b'n' {
-			s[i] = b'\n';
-			s.copy_within(i + 2.., i + 1);
-			n += 1;
+pub fn unescape(s: &mut [u8]) -> &mut [u8] {
+	let (mut read, mut written) = (0, 0);
+	while read < s.len() {
+		let mut c = s[read];
+		if c == b'\\' && read + 1 < s.len() {
+			let escaped = match s[read + 1] {
+				b'n' => Some(b'\n'),
+				b't' => Some(b'\t'),
+				b'r' => Some(b'\r'),
+				c @ (b'"' | b'\\' | b'/') => Some(c),
+				_ => None,
+			};
+			if let Some(escaped) = escaped {
+				c = escaped;
+				read += 1;
+			}
 		}
+		s[written] = c;
+		written += 1;
+		read += 1;
 	}
-	let new_len = s.len() - n;
-	&mut s[..new_len]
+	&mut s[..written]
 }
 
 #[cfg(test)]
 mod test {
 	use super::*;
 
+	#[test]
+	fn test_parse_jwk() {
+		let body = format!(r#"{{"keys":[{{"kty":"RSA","alg":"RS256","use":"sig","kid":"a","n":"{}","e":"{}"}}]}}"#, crate::testing::RSA_A_N, crate::testing::RSA_A_E);
+		let keys = ParseJwk::new(body.as_bytes()).unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+		assert_eq!(keys.len(), 1);
+		assert_eq!(keys[0].0, "a");
+		let token = crate::testing::sign(Some("a"), crate::testing::RSA_A_KEY, &crate::testing::claims(3600));
+		let mut validation = jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::RS256);
+		validation.validate_aud = false;
+		jsonwebtoken::decode::<serde_json::Value>(&token, &keys[0].1, &validation).unwrap();
+		assert!(ParseJwk::new(b"{}").is_err());
+	}
+
+	#[test]
+	fn test_parse_stays_in_bounds() {
+		// The quotes past the parsed slice must not be mistaken for another entry.
+		let mut data = *br#"{"a": "x", "b": "y"} "c": "z""#;
+		let len = data.len() - r#" "c": "z""#.len();
+		let entries = Parse::new(&mut data[..len]).collect::<Vec<_>>();
+		assert_eq!(entries, [(&b"a"[..], &b"x"[..]), (&b"b"[..], &b"y"[..])]);
+	}
+
+	#[test]
+	fn test_pem_components() {
+		assert_eq!(pem_components(crate::testing::RSA_A_CERT.as_bytes()), Some(Components::Rsa {
+			n: crate::testing::RSA_A_N.to_owned(),
+			e: crate::testing::RSA_A_E.to_owned(),
+		}));
+		assert!(matches!(pem_components(crate::testing::EC_CERT.as_bytes()), Some(Components::Ec { crv: "P-256", .. })));
+		assert_eq!(pem_components(b"-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----\n"), None);
+	}
+
+	#[test]
+	fn test_parse_owned() {
+		let body = crate::testing::certs_body([("b", crate::testing::RSA_B_CERT), ("a", crate::testing::RSA_A_CERT)]);
+		let entries = parse_owned(&body).unwrap();
+		assert_eq!(entries, [
+			("b".to_owned(), crate::testing::RSA_B_CERT.as_bytes().to_vec()),
+			("a".to_owned(), crate::testing::RSA_A_CERT.as_bytes().to_vec()),
+		]);
+		assert!(parse_owned(br#"{"a": "x""#).is_err());
+		assert!(parse_owned(br#"{"a": 1}"#).is_err());
+	}
+
+	#[test]
+	fn test_try_parse() {
+		let mut body = crate::testing::certs_body([("a", "1"), ("b", "2")]);
+		let entries = TryParse::new(&mut body).collect::<Result<Vec<_>, _>>().unwrap();
+		assert_eq!(entries, [(&b"a"[..], &b"1"[..]), (b"b", b"2")]);
+		assert_eq!(TryParse::new(&mut b" { } ".to_vec()).count(), 0);
+
+		for malformed in [&br#""#[..], b"<html>", br#"{"a": "1", "b": "2"#, br#"{"a": "1","#, br#"{"a": "1""#, br#"{"a": "1"} x"#, br#"{"a": "1",}"#] {
+			let mut malformed = malformed.to_vec();
+			let last = TryParse::new(&mut malformed).last().unwrap();
+			assert!(matches!(last, Err(ParseError::Malformed)), "{:?}", std::str::from_utf8(&malformed));
+		}
+		let mut truncated = br#"{"a": "1", "b"#.to_vec();
+		let mut parse = TryParse::new(&mut truncated);
+		assert!(matches!(parse.next(), Some(Ok((b"a", b"1")))));
+		assert!(matches!(parse.next(), Some(Err(ParseError::Malformed))));
+		assert!(parse.next().is_none());
+	}
+
+	#[test]
+	fn test_parse_checked() {
+		let mut body = crate::testing::certs_body([("a", "1")]);
+		assert_eq!(Parse::checked(&mut body).unwrap().collect::<Vec<_>>(), [(&b"a"[..], &b"1"[..])]);
+		assert_eq!(Parse::checked(&mut b" {} ".to_vec()).unwrap().count(), 0);
+		for malformed in [&b""[..], b"HTTP/1.0 200 OK\r\n\r\n{}", b"<html></html>", b"{a}", br#"["a", "1"]"#] {
+			assert!(matches!(Parse::checked(&mut malformed.to_vec()), Err(ParseError::Malformed)), "{:?}", std::str::from_utf8(malformed));
+		}
+	}
+
+	/// Holds on to every entry while parsing the next ones, which Miri checks for aliasing.
+	///
+	/// Run the parsing tests under Miri with
+	/// `cargo +nightly miri test --no-default-features parse::test -- --skip test_parse_jwk`
+	/// (that one validates a signature, which Miri can't run).
+	#[test]
+	fn test_parse_entries_outlive_iteration() {
+		let mut data = *br#"{"a": "1\n2", "b\"": "3", "c": "4"} "d": "5""#;
+		let len = data.len() - r#" "d": "5""#.len();
+		let mut parse = Parse::new(&mut data[..len]);
+		let a = parse.next().unwrap();
+		let b = parse.next().unwrap();
+		let c = parse.next().unwrap();
+		assert!(parse.next().is_none());
+		assert!(parse.next().is_none());
+		assert_eq!([a, b, c], [(&b"a"[..], &b"1\n2"[..]), (&b"b\""[..], &b"3"[..]), (&b"c"[..], &b"4"[..])]);
+	}
+
 	#[test]
 	fn test_unescape() {
 		let mut s = *b"hello\\nworld\\n";
 		let s = unescape(&mut s);
-		assert_eq!(&s[..], b"hello\nworld\n")
+		assert_eq!(&s[..], b"hello\nworld\n");
+		let mut s = *br#"\t\r\"\\\/\u0041\"#;
+		let s = unescape(&mut s);
+		assert_eq!(&s[..], b"\t\r\"\\/\\u0041\\");
+	}
+
+	#[test]
+	fn test_parse_escaped_quotes() {
+		let mut data = *br#"{"a\"b": "x\"\\", "c": "y"}"#;
+		let entries = Parse::new(&mut data).collect::<Vec<_>>();
+		assert_eq!(entries, [(&br#"a"b"#[..], &br#"x"\"#[..]), (&b"c"[..], &b"y"[..])]);
 	}
 }
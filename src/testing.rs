@@ -0,0 +1,94 @@
+//! Test fixtures: self-signed certificates with their private keys, and token signing.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A self-signed RSA certificate.
+pub const RSA_A_CERT: &str = include_str!("../testdata/rsa_a.crt");
+/// The private key of [`RSA_A_CERT`].
+pub const RSA_A_KEY: &str = include_str!("../testdata/rsa_a.key");
+/// The base64url RSA modulus of [`RSA_A_CERT`].
+pub const RSA_A_N: &str = "ldufy86RagnL_9sOlAMXDg-Ashz5_cpbduJPo2C1aPhjjYmj8vwsNOnF4ncaRH776327mcC9pDcjxY-mRsuC3UZU0HwnIkl0MFK2FqRAJ9M5R5hm9xYO0JzTE3aQARQ9foNbIKSj1pZPwCb70RpAn6lCTUXOqrQwVaMyGXha7BM5P_Ah6pJC1n02W61kBXAzv_93RlvMUTIl1uTNr6zphevxd1E1muLjUmKprDUGCp4dNLYK8-R1epbSa8m9-0x3KZRfVrg-TbYsOHnV_hnwIj1fB-pKmsia7EtA9ezd3II1ou6O7_YztegTnZbN4zR3-4mvoJdDbOtTVToVVHv-Hw";
+/// The base64url RSA exponent of [`RSA_A_CERT`].
+pub const RSA_A_E: &str = "AQAB";
+/// Another self-signed RSA certificate.
+pub const RSA_B_CERT: &str = include_str!("../testdata/rsa_b.crt");
+/// A self-signed P-256 EC certificate.
+pub const EC_CERT: &str = include_str!("../testdata/ec.crt");
+/// The private key of [`EC_CERT`].
+pub const EC_KEY: &str = include_str!("../testdata/ec.key");
+
+/// The current UNIX timestamp.
+pub fn now() -> u64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+/// Google-like claims expiring `exp` seconds from now (negative for the past).
+pub fn claims(exp: i64) -> serde_json::Value {
+	serde_json::json!({
+		"iss": "https://accounts.google.com",
+		"sub": "1234567890",
+		"aud": "client.apps.googleusercontent.com",
+		"email": "user@example.com",
+		"email_verified": true,
+		"iat": now() as i64 - 60,
+		"exp": now() as i64 + exp,
+	})
+}
+
+/// Signs the claims with the RSA private key, as an RS256 token.
+pub fn sign(kid: Option<&str>, key: &str, claims: &serde_json::Value) -> String {
+	let mut header = jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256);
+	header.kid = kid.map(str::to_owned);
+	let key = jsonwebtoken::EncodingKey::from_rsa_pem(key.as_bytes()).unwrap();
+	jsonwebtoken::encode(&header, claims, &key).unwrap()
+}
+
+/// Signs the claims with the EC private key, as an ES256 token.
+pub fn sign_ec(kid: Option<&str>, key: &str, claims: &serde_json::Value) -> String {
+	let mut header = jsonwebtoken::Header::new(jsonwebtoken::Algorithm::ES256);
+	header.kid = kid.map(str::to_owned);
+	let key = jsonwebtoken::EncodingKey::from_ec_pem(key.as_bytes()).unwrap();
+	jsonwebtoken::encode(&header, claims, &key).unwrap()
+}
+
+/// [`crate::keys::Keys`] holding [`RSA_A_CERT`] as `"a"` and [`RSA_B_CERT`] as `"b"`.
+pub fn keys() -> crate::keys::Keys {
+	let mut keys = crate::keys::Keys::<{ crate::keys::KEYS_CAPACITY }>::new();
+	keys.push(b"a", RSA_A_CERT.as_bytes()).unwrap();
+	keys.push(b"b", RSA_B_CERT.as_bytes()).unwrap();
+	keys
+}
+
+/// Builds a certs endpoint body out of (key ID, PEM) pairs.
+pub fn certs_body<'a>(keys: impl IntoIterator<Item = (&'a str, &'a str)>) -> Vec<u8> {
+	let entries = keys.into_iter()
+		.map(|(id, pem)| format!("  \"{id}\": \"{}\"", pem.replace('\n', "\\n")))
+		.collect::<Vec<_>>();
+	format!("{{\n{}\n}}\n", entries.join(",\n")).into_bytes()
+}
+
+#[cfg(feature = "cache")]
+thread_local! {
+	static MOCK_NOW: std::cell::Cell<u64> = const { std::cell::Cell::new(0) };
+}
+
+/// A [`crate::fetch::Instant`] in seconds, whose (per-thread) clock is set by [`MockInstant::set`].
+#[cfg(feature = "cache")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MockInstant(pub u64);
+
+#[cfg(feature = "cache")]
+impl MockInstant {
+	/// Sets the current time.
+	pub fn set(now: u64) { MOCK_NOW.with(|now_cell| now_cell.set(now)); }
+}
+
+#[cfg(feature = "cache")]
+impl crate::fetch::Instant for MockInstant {
+	fn now() -> Self { Self(MOCK_NOW.with(std::cell::Cell::get)) }
+	fn is_before(&self, other: &Self) -> bool { self.0 < other.0 }
+	fn add_seconds(&mut self, seconds: u64) { self.0 += seconds; }
+	fn saturating_duration_since(&self, earlier: &Self) -> std::time::Duration {
+		std::time::Duration::from_secs(self.0.saturating_sub(earlier.0))
+	}
+}
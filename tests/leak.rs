@@ -0,0 +1,104 @@
+//! Checks that dropping and clearing [`Keys`] frees its keys, and that rejecting tokens of
+//! unknown keys doesn't allocate.
+
+use std::{alloc::{GlobalAlloc, Layout, System}, cell::Cell};
+
+use google_pem::keys::Keys;
+
+/// An allocator that counts the allocations of the [measured](measure) thread.
+struct Counting;
+
+/// The allocations of a thread.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Measured {
+	/// The bytes allocated less those freed.
+	in_use: isize,
+	/// The count of allocations.
+	allocations: usize,
+}
+
+thread_local! {
+	/// The allocations of the thread, while it's [measured](measure).
+	///
+	/// Being per thread, the harness's own threads don't count.
+	static MEASURED: Cell<Option<Measured>> = const { Cell::new(None) };
+}
+
+/// Measures the allocations `f` makes on the current thread.
+fn measure(f: impl FnOnce()) -> Measured {
+	MEASURED.set(Some(Measured::default()));
+	f();
+	MEASURED.take().unwrap()
+}
+
+/// Counts an allocation (positive `size`) or deallocation (negative) if the thread is measured.
+fn count(size: isize) {
+	// The thread local may already be gone when the thread exits.
+	let _ = MEASURED.try_with(|measured| if let Some(mut m) = measured.get() {
+		m.in_use += size;
+		m.allocations += usize::from(size > 0);
+		measured.set(Some(m));
+	});
+}
+
+unsafe impl GlobalAlloc for Counting {
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+		count(layout.size() as isize);
+		System.alloc(layout)
+	}
+
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+		count(-(layout.size() as isize));
+		System.dealloc(ptr, layout)
+	}
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+const RSA_A_CERT: &str = include_str!("../testdata/rsa_a.crt");
+const EC_CERT: &str = include_str!("../testdata/ec.crt");
+
+fn fill(keys: &mut Keys<2>) {
+	keys.push(b"a", RSA_A_CERT.as_bytes()).unwrap();
+	keys.push(b"ec", EC_CERT.as_bytes()).unwrap();
+}
+
+#[test]
+fn test_drop_frees_keys() {
+	let new = || {
+		let mut keys = Keys::<2>::new();
+		fill(&mut keys);
+		keys
+	};
+	drop(new()); // warms up lazily allocated state, if any
+
+	let measured = measure(|| for _ in 0..100 { drop(new()); });
+	assert_eq!(measured.in_use, 0);
+}
+
+#[test]
+fn test_clear_frees_keys() {
+	let mut keys = Keys::<2>::new();
+	fill(&mut keys);
+	keys.clear();
+
+	let measured = measure(|| for _ in 0..100 {
+		fill(&mut keys);
+		keys.clear();
+	});
+	assert_eq!(measured.in_use, 0);
+}
+
+#[test]
+fn test_unknown_key_no_alloc() {
+	let mut keys = Keys::<2>::new();
+	fill(&mut keys);
+	// {"alg":"RS256","kid":"unknown","typ":"JWT"}
+	let token = "eyJhbGciOiJSUzI1NiIsImtpZCI6InVua25vd24iLCJ0eXAiOiJKV1QifQ.payload.signature";
+
+	let validate = || assert!(matches!(keys.validate::<()>(token), Err(google_pem::keys::ValidateError::UnknownKey)));
+	validate(); // warms up the lazily built validation
+
+	assert_eq!(measure(validate).allocations, 0);
+}
@@ -0,0 +1,108 @@
+//! Fetches canned keys (directly, and through a discovery document) from a local TLS server, and
+//! validates tokens with them, end to end.
+#![cfg(feature = "net")]
+
+use std::sync::Arc;
+
+use google_pem::{jsonwebtoken, keys::Keys};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_rustls::rustls::{self, pki_types::{CertificateDer, PrivatePkcs8KeyDer}};
+
+/// A response of the PEM endpoint.
+static SAMPLE: &[u8] = include_bytes!("../testdata/sample_response.http");
+
+/// Binds a local port, returning its listener and host.
+async fn bind() -> (tokio::net::TcpListener, String) {
+	let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+	let host = format!("127.0.0.1:{}", listener.local_addr().unwrap().port());
+	(listener, host)
+}
+
+/// Serves the responses over TLS, as `localhost.crt`, to a connection each.
+///
+/// Returns a handle to the requests.
+fn serve(listener: tokio::net::TcpListener, responses: Vec<Vec<u8>>) -> tokio::task::JoinHandle<Vec<Vec<u8>>> {
+	let cert = CertificateDer::from(&include_bytes!("../testdata/localhost.crt.der")[..]);
+	let key = PrivatePkcs8KeyDer::from(&include_bytes!("../testdata/localhost.key.der")[..]).into();
+	let config = rustls::ServerConfig::builder().with_no_client_auth().with_single_cert(vec![cert], key).unwrap();
+	let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+	tokio::spawn(async move {
+		let mut requests = Vec::with_capacity(responses.len());
+		for response in responses {
+			let (stream, _) = listener.accept().await.unwrap();
+			let mut stream = acceptor.accept(stream).await.unwrap();
+			let mut request = Vec::new();
+			while !request.ends_with(b"\r\n\r\n") {
+				stream.read_buf(&mut request).await.unwrap();
+			}
+			stream.write_all(&response).await.unwrap();
+			stream.shutdown().await.unwrap();
+			requests.push(request);
+		}
+		requests
+	})
+}
+
+/// A client configuration that trusts only the local server.
+fn client_config() -> Arc<rustls::ClientConfig> {
+	let mut roots = rustls::RootCertStore::empty();
+	roots.add(CertificateDer::from(&include_bytes!("../testdata/localhost.crt.der")[..])).unwrap();
+	Arc::new(rustls::ClientConfig::builder().with_root_certificates(roots).with_no_client_auth())
+}
+
+/// A token signed by `rsa_a.key`, as key `"a"`.
+fn token(iss: &str) -> String {
+	let header = jsonwebtoken::Header { kid: Some("a".to_owned()), ..jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256) };
+	let claims = serde_json::json!({ "iss": iss, "sub": "1234567890", "exp": jsonwebtoken::get_current_timestamp() + 3600 });
+	let key = jsonwebtoken::EncodingKey::from_rsa_pem(include_bytes!("../testdata/rsa_a.key")).unwrap();
+	jsonwebtoken::encode(&header, &claims, &key).unwrap()
+}
+
+#[tokio::test]
+async fn test_fetch_parse_validate() {
+	// The sample's headers, with keys we have the private key of.
+	let cert = include_str!("../testdata/rsa_a.crt").replace('\n', "\\n");
+	let mut response = SAMPLE[..google_pem::fetch::body(SAMPLE).unwrap()].to_vec();
+	response.extend(format!("{{\n  \"a\": \"{cert}\"\n}}\n").into_bytes());
+	let (listener, host) = bind().await;
+	let server = serve(listener, vec![response]);
+
+	let mut buffer = [0u8; 5 << 10];
+	let len = google_pem::fetch::with_config(client_config()).into_from(&host, "/oauth2/v1/certs", &mut buffer).await.unwrap();
+	let requests = server.await.unwrap();
+	assert!(requests[0].starts_with(b"GET /oauth2/v1/certs HTTP/1.0\r\n"));
+
+	let response = &mut buffer[..len];
+	let (age, body) = google_pem::fetch::process_headers(response).unwrap();
+	assert_eq!(age, google_pem::fetch::Age { age: 9, max_age: 22270 });
+	let mut keys = Keys::with_default_capacity();
+	assert!(keys.try_extend(google_pem::parse(&mut response[body..])).unwrap());
+
+	let data = keys.validate::<serde_json::Value>(&token("https://accounts.google.com")).unwrap();
+	assert_eq!(data.claims["sub"], "1234567890");
+}
+
+#[tokio::test]
+async fn test_discovery() {
+	let (listener, host) = bind().await;
+	let issuer = format!("https://{host}");
+	let document = format!(r#"{{"issuer": "{issuer}", "jwks_uri": "https://{host}/jwks"}}"#);
+	let mut keys = Keys::with_default_capacity();
+	keys.push(b"a", include_bytes!("../testdata/rsa_a.crt")).unwrap();
+	let jwks = keys.to_jwks_json();
+	let server = serve(listener, vec![
+		format!("HTTP/1.0 200 OK\r\nCache-Control: public, max-age=3600\r\n\r\n{document}").into_bytes(),
+		format!("HTTP/1.0 200 OK\r\nCache-Control: public, max-age=100\r\n\r\n{jwks}").into_bytes(),
+	]);
+
+	let connector = tokio_rustls::TlsConnector::from(client_config());
+	let discovered = google_pem::discovery::fetch_and_build_with::<4>(&connector, &host, google_pem::discovery::PATH).await.unwrap();
+	let requests = server.await.unwrap();
+	assert!(requests[0].starts_with(b"GET /.well-known/openid-configuration HTTP/1.0\r\n"));
+	assert!(requests[1].starts_with(b"GET /jwks HTTP/1.0\r\n"));
+	assert_eq!(discovered.issuer, issuer);
+	assert_eq!(discovered.age.max_age, 100);
+
+	discovered.validate::<serde_json::Value>(&token(&issuer)).unwrap();
+	assert!(discovered.validate::<serde_json::Value>(&token("https://accounts.google.com")).is_err());
+}